@@ -1,28 +1,43 @@
-use std::collections::{HashMap, HashSet};
-use std::fmt::Error;
-use std::fs;
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::ffi::OsString;
+use std::io::{self, IsTerminal};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::path::{Path, PathBuf};
-use log::{info, error};
-use config::{File, Environment, FileFormat, Value};
-use multiaddr::{Multiaddr};
-use handlebars::Handlebars;
+use std::str::FromStr;
+use log::LevelFilter;
+use config::{File, FileFormat, Value};
+use multiaddr::{Multiaddr, Protocol};
+use handlebars::{Handlebars, handlebars_helper};
+use url::Url;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use crate::config::config_file::DEFAULT_CONFIG_TEMPLATE;
+use crate::config::config_file::{DEFAULT_CONFIG_TEMPLATE, default_root_dir, is_dangling_symlink};
 
-use crate::config::config_utils::{ByteSize, expand_home_dir};
+use yaml_rust::{ScanError, YamlEmitter};
+use crate::config::config_utils::{ByteSize, GiB, MiB, deserialize_string_list, expand_home_dir, leaf_keys, EnvSource, is_lowercase_alpha, json_to_yaml, parse_kv};
 use crate::config::errors::ConfigError;
+use crate::datastore::{DatastoreError, Error};
+use crate::datastore::badger::v4::errors::BadgerError;
+use crate::datastore::iterable::IterableTxnDatastore;
+use crate::datastore::memory::MemoryStore;
 
 const DEFAULT_API_EMAIL: &str = "example@example.com";
-const ROOTDIR_KEY: &str = "rootdircli";
 const DEFRA_ENV_PREFIX: &str = "DEFRA";
+const RUST_LOG_ENV: &str = "RUST_LOG";
+const NO_COLOR_ENV: &str = "NO_COLOR";
+const REDACTED: &str = "***";
+const MIN_MEMORY_STORE_SIZE: ByteSize = MiB;
+const MAX_TXN_RETRIES: i32 = 100;
+const LOG_LEVEL_TRACE: &str = "trace";
 const LOG_LEVEL_DEBUG: &str = "debug";
 const LOG_LEVEL_INFO: &str = "info";
+const LOG_LEVEL_WARN: &str = "warn";
 const LOG_LEVEL_ERROR: &str = "error";
 const LOG_LEVEL_FATAL: &str = "fatal";
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub datastore: DatastoreConfig,
     pub api: APIConfig,
@@ -32,51 +47,72 @@ pub struct Config {
 
     #[serde(skip)]
     pub config: config::Config,
+
+    // The peers as set by the config file, before the environment variables are merged on top of it.
+    #[serde(skip)]
+    file_peers: Option<String>,
 }
 
 impl Config {
     pub fn default_config() -> Result<Self, ConfigError> {
         let mut config = config::Config::default();
 
-        // TODO: add default config
-        // config.set_default("Datastore", DatastoreConfig::default_data_store_config())?;
-
-        config.set_default("API", json!(APIConfig::default_api_config()).as_str().unwrap().to_string())
-            .map_err(|e| ConfigError::Custom(format!("Failed to set default api config: {}", e)))?;
+        config.set_default("datastore", to_config_value(&DatastoreConfig::default_data_store_config())?).map_err(ConfigError::Config)?;
 
-        config.set_default("Net", json!(NetConfig::default_net_config()).as_str().unwrap().to_string())
-            .map_err(|e| ConfigError::Custom(format!("Failed to set default net config: {}", e)))?;
+        config.set_default("api", to_config_value(&APIConfig::default_api_config())?).map_err(ConfigError::Config)?;
 
-        config.set_default("Log", json!(LoggingConfig::default_log_config()).as_str().unwrap().to_string())
-            .map_err(|e| ConfigError::Custom(format!("Failed to set default log config: {}", e)))?;
+        config.set_default("net", to_config_value(&NetConfig::default_net_config())?).map_err(ConfigError::Config)?;
 
-        config.set_default("Rootdir", "".to_string())
-            .map_err(|e| ConfigError::Custom(format!("Failed to set default rootdir: {}", e)))?;
+        config.set_default("log", to_config_value(&LoggingConfig::default_log_config())?).map_err(ConfigError::Config)?;
 
-        // TODO: find equivalents fo the same
-        // config.set_env_prefix("defra_env_prefix");
-        // config.set_env_replacer("_", ".");
+        config.set_default("rootdir", "".to_string()).map_err(ConfigError::Config)?;
 
-        config.merge(File::new("DefaultConfigFileName", FileFormat::Toml)).map_err(|e| ConfigError::Custom(format!("Failed to merge default config file: {}", e)))?;
+        // Environment variables override the defaults, e.g. `DEFRA_API_ADDRESS` maps to `api.address` and
+        // `DEFRA_NET_P2P_DISABLED` to `net.p2p_disabled`, see `EnvSource`.
+        config.merge(env_source()).map_err(source_error)?;
 
         let cfg = Config {
-            datastore: config.get("Datastore").map_err(|e| ConfigError::Custom(format!("Failed to get datastore: {}", e)))?,
-            api: config.get("API").map_err(|e| ConfigError::Custom(format!("Failed to get api: {}", e)))?,
-            net: config.get("Net").map_err(|e| ConfigError::Custom(format!("Failed to get net: {}", e)))?,
-            log: config.get("Log").map_err(|e| ConfigError::Custom(format!("Failed to get log: {}", e)))?,
-            rootdir: config.get("Rootdir").map_err(|e| ConfigError::Custom(format!("Failed to get rootdir: {}", e)))?,
+            datastore: get_section(&config, "datastore")?,
+            api: get_section(&config, "api")?,
+            net: get_section(&config, "net")?,
+            log: get_section(&config, "log")?,
+            rootdir: get_section(&config, "rootdir")?,
             config,
+            file_peers: None,
         };
 
         Ok(cfg)
     }
 
+    // Builds the default config rooted at `rootdir` (or the default root directory when empty) and loads it.
+    pub fn default_with_rootdir(rootdir: &str) -> Result<Self, ConfigError> {
+        let mut cfg = Self::default_config()?;
+        cfg.rootdir = rootdir.to_string();
+        cfg.load_with_rootdir(true)?;
+        Ok(cfg)
+    }
+
+    /// Builds the config from the defaults and the `DEFRA_*` environment variables only, the config file is never
+    /// read. The rootdir is `DEFRA_ROOTDIR`, or the default root directory when it isn't set.
+    pub fn from_env_only() -> Result<Self, ConfigError> {
+        let mut cfg = Self::default_config()?;
+        cfg.load_with_rootdir(false)?;
+        Ok(cfg)
+    }
+
     pub fn load_with_rootdir(&mut self, with_rootdir: bool) -> Result<(), ConfigError> {
-        if with_rootdir {
-            self.config.merge(File::with_name(self.rootdir.as_str())).map_err(|e| ConfigError::Custom(format!("Failed to merge config file: {}", e)))?;
+        let rootdir = if self.rootdir.is_empty() {
+            default_root_dir().to_string_lossy().into_owned()
+        } else {
+            self.rootdir.clone()
+        };
+        self.set_rootdir(&rootdir)?;
+
+        // A missing config file is fine and leaves the defaults in place, an unreadable one is not.
+        if with_rootdir && self.config_file_exists() {
+            self.merge_file(Path::new(&self.config_file_path()), Some(FileFormat::Yaml))?;
         }
 
-        self.config.clone().try_into::<Self>().map_err(|e| ConfigError::Custom(format!("Failed to load config: {}", e)))?;
         self.validate()?;
         self.params_preprocessing()?;
         self.load()?;
@@ -84,18 +120,94 @@ impl Config {
         Ok(())
     }
 
-    //TODO: Load root directory from flag or default
+    pub fn merge_file(&mut self, path: &Path, format: Option<FileFormat>) -> Result<(), ConfigError> {
+        let mut file = File::from(path);
+        if let Some(format) = format {
+            file = file.format(format);
+        }
+
+        // Merge into a copy, a failed merge would otherwise stay registered as a source.
+        let mut config = self.config.clone();
+        config.merge(file).map_err(source_error)?;
+
+        // Environment variables take precedence over the file, so they are merged again on top of it.
+        let file_peers = config.get_str("net.peers").ok();
+        config.merge(env_source()).map_err(source_error)?;
 
-    fn set_rootdir(&mut self, rootdir: &str) -> Result<(), ConfigError> {
+        let cfg = config.clone().try_into::<Self>().map_err(ConfigError::LoadingConfig)?;
+        self.datastore = cfg.datastore;
+        self.api = cfg.api;
+        self.net = cfg.net;
+        self.log = cfg.log;
+        self.rootdir = cfg.rootdir;
+        self.config = config;
+        self.file_peers = file_peers;
+
+        Ok(())
+    }
+
+    pub fn set_rootdir(&mut self, rootdir: &str) -> Result<(), ConfigError> {
         if rootdir.is_empty() {
-            return Err(ConfigError::InvalidRootDir(rootdir.to_string()).into());
+            return Err(ConfigError::InvalidRootDir(rootdir.to_string()));
         }
 
-        self.rootdir = fs::canonicalize(rootdir).map(|p| p.to_str().unwrap().to_string()).map_err(|e| ConfigError::Custom(format!("Failed to canonicalize rootdir: {}", e)))?;
-        self.config.set_default("rootdir", self.rootdir.clone()).map_err(|e| ConfigError::Custom(format!("Failed to set rootdir: {}", e)))?;
+        // The rootdir doesn't have to exist yet, in which case it can't be canonicalized.
+        let path = match fs::canonicalize(rootdir) {
+            Ok(path) => path,
+            Err(_) if is_dangling_symlink(Path::new(rootdir)) => {
+                return Err(ConfigError::InvalidRootDir(format!("{} is a symlink to a missing target", rootdir)));
+            }
+            Err(_) => std::path::absolute(rootdir)?,
+        };
+        self.rootdir = path.to_string_lossy().into_owned();
+        // Setting a default re-reads every source, including a config file that may have become malformed.
+        self.config.set_default("rootdir", self.rootdir.clone()).map_err(source_error)?;
         Ok(())
     }
 
+    /// Returns the normalized base URL of the API, e.g. `http://localhost:9181/`.
+    /// With port 0 the URL doesn't contain the effective port, which is only known once the server is bound.
+    pub fn api_base_url(&self) -> Result<String, ConfigError> {
+        let url = Url::parse(&self.api.address_to_url()).map_err(|_| ConfigError::InvalidDatabaseURL)?;
+        if !url.has_host() {
+            return Err(ConfigError::InvalidDatabaseURL);
+        }
+        Ok(url.into())
+    }
+
+    pub fn p2p_enabled(&self) -> bool {
+        !self.net.p2p_disabled
+    }
+
+    /// Returns the bootstrap peers. By default `DEFRA_NET_PEERS` replaces the peers of the config file, with
+    /// `net.union_env_peers` set the peers of both are combined, file peers first and without duplicates.
+    pub fn effective_peers(&self) -> Result<Vec<Multiaddr>, ConfigError> {
+        let env_peers = std::env::var(format!("{}_NET_PEERS", DEFRA_ENV_PREFIX));
+        let (true, Some(file_peers), Ok(env_peers)) = (self.net.union_env_peers, &self.file_peers, env_peers) else {
+            return self.net.parse_peers();
+        };
+
+        let mut peers = Vec::new();
+        for peer in parse_peer_list(file_peers)?.into_iter().chain(parse_peer_list(&env_peers)?) {
+            if !peers.contains(&peer) {
+                peers.push(peer);
+            }
+        }
+        Ok(peers)
+    }
+
+    /// Returns the bootstrap peers without the node's own listen addresses, so it never dials itself.
+    /// Peers are matched by their exact multiaddr, a peer listed under another address of this node is kept.
+    pub fn bootstrap_peers_with_self_filter(&self) -> Result<Vec<Multiaddr>, ConfigError> {
+        let own = self.net.p2p_multiaddrs()?;
+        Ok(self.net.parse_peers()?.into_iter().filter(|peer| !own.contains(peer)).collect())
+    }
+
+    /// Validates every section without preprocessing or loading the config.
+    pub fn validate_only(&self) -> Result<(), ConfigError> {
+        self.validate()
+    }
+
     fn validate(&self) -> Result<(), ConfigError> {
         self.datastore.validate()?;
         self.api.validate()?;
@@ -105,49 +217,133 @@ impl Config {
     }
 
     fn params_preprocessing(&mut self) -> Result<(), ConfigError> {
-        let mut update_path = |key: &str| {
-            let mut path = self.config.get::<String>(key).unwrap_or_default();
-            if !Path::new(&path).is_absolute() {
-                self.config.set(key, self.rootdir.clone()  + path.as_str()).unwrap();
+        let rootdir = PathBuf::from(&self.rootdir);
+        let update_path = |path: &mut String| {
+            if !Path::new(path.as_str()).is_absolute() {
+                *path = rootdir.join(path.as_str()).to_string_lossy().into_owned();
             }
         };
 
-        update_path("datastore.badger.path");
-        update_path("api.privkeypath");
-        update_path("api.pubkeypath");
+        // The home directory has to be expanded first, `~/data` would otherwise be joined onto the rootdir.
+        expand_home_dir(&mut self.datastore.badger.path)?;
+        expand_home_dir(&mut self.api.priv_key_path)?;
+        expand_home_dir(&mut self.api.pub_key_path)?;
+
+        update_path(&mut self.datastore.badger.path);
+        update_path(&mut self.api.priv_key_path);
+        update_path(&mut self.api.pub_key_path);
+        self.log.resolve_outputs(&rootdir);
 
         if let Ok(loglogger_as_string_slice) = self.config.get::<Vec<String>>("log.logger") {
             let combined = loglogger_as_string_slice.join(";");
             self.config.set("log.logger", combined).unwrap();
         }
 
-        // Assuming expand_home_dir exists
-        expand_home_dir(&mut self.api.priv_key_path).map_err(|e| ConfigError::Custom(format!("Unable to expand home directory: {}", e)))?;
-        expand_home_dir(&mut self.api.pub_key_path).map_err(|e| ConfigError::Custom(format!("Unable to expand home directory: {}", e)))?;
-
-        // Assuming ByteSize and its set() method exist
-        let mut bs = ByteSize::default();
-        let value = self.config.get::<String>("datastore.badger.valuelogfilesize").unwrap_or_default();
-        bs.set(&value)?;
-        self.datastore.badger.value_log_file_size = bs;
-
         Ok(())
     }
 
     fn load(&mut self) -> Result<(), ConfigError> {
+        // The badger and TLS paths are only checked once absolute, which they are after preprocessing.
+        self.datastore.validate()?;
+        self.datastore.check_badger_writable()?;
+        self.api.validate()?;
         self.log.load()?;
         Ok(())
     }
 
     pub fn to_bytes(&self) -> Result<Vec<u8>, ConfigError> {
         let mut handlebars = Handlebars::new();
+        // A field missing from the config, e.g. after renaming it, fails the rendering instead of rendering empty.
+        handlebars.set_strict_mode(true);
+        // Values are written as JSON, which YAML reads as is, so they must not be HTML escaped.
+        handlebars.register_escape_fn(handlebars::no_escape);
+        handlebars.register_helper("yaml", Box::new(yaml_helper));
+
         let config_template = DEFAULT_CONFIG_TEMPLATE;
-        handlebars.register_template_string("configTemplate", config_template).map_err(|e| ConfigError::Custom(format!("Could not register config template: {}", e)))?;
+        handlebars
+            .register_template_string("configTemplate", config_template)
+            .map_err(|e| ConfigError::ConfigTemplateFailed(e.to_string()))?;
 
-        let rendered = handlebars.render("configTemplate", &self).map_err(|e| ConfigError::Custom(format!("Could not process config template: {}", e)))?;
+        let rendered = handlebars
+            .render("configTemplate", &self)
+            .map_err(|e| ConfigError::ConfigTemplateFailed(e.to_string()))?;
 
         Ok(rendered.into_bytes())
     }
+
+    /// Brings the config to its canonical form: paths are absolute and log levels are flattened, with every named
+    /// logger spelled out in full in the `logger` setting. A normalized config is written by `to_bytes` without loss,
+    /// reading the file back and normalizing it yields the same config.
+    pub fn normalize(&mut self) -> Result<(), ConfigError> {
+        self.params_preprocessing()?;
+        self.log.normalize()
+    }
+
+    /// Renders the config as YAML with paths resolved and log levels flattened, as it is in effect once loaded.
+    /// Secrets are redacted, see `sanitize`.
+    pub fn dump_effective(&self) -> Result<String, ConfigError> {
+        let mut effective = self.clone();
+        effective.params_preprocessing()?;
+        effective.log.resolve()?;
+        effective.redact_secrets();
+
+        let value = serde_json::to_value(&effective).map_err(ConfigError::ConfigToJSONFailed)?;
+        let mut out = String::new();
+        YamlEmitter::new(&mut out)
+            .dump(&json_to_yaml(value))
+            .map_err(|e| ConfigError::Custom(format!("Could not write config as YAML: {:?}", e)))?;
+        Ok(out)
+    }
+
+    /// Deserializes a single section of the merged config, e.g. `net` into a `NetConfig`. Names are case-insensitive.
+    pub fn get_section<T: DeserializeOwned>(&self, name: &str) -> Result<T, ConfigError> {
+        get_section(&self.config, name)
+    }
+
+    /// Returns a copy that is safe to log, with the secret settings replaced by `***`. Unset secrets stay empty.
+    /// The copy has an empty inner `config::Config`, as that holds the raw values.
+    pub fn sanitize(&self) -> Config {
+        let mut sanitized = self.clone();
+        sanitized.redact_secrets();
+        sanitized.config = config::Config::default();
+        sanitized
+    }
+
+    fn redact_secrets(&mut self) {
+        for secret in [&mut self.api.priv_key_path] {
+            if !secret.is_empty() {
+                *secret = REDACTED.to_string();
+            }
+        }
+    }
+
+    /// Lists the settings that differ between `self` and `other`, by their dotted path, e.g. `log.level`.
+    pub fn diff(&self, other: &Config) -> Result<Vec<ConfigChange>, ConfigError> {
+        let old = serde_json::to_value(self).map_err(ConfigError::ConfigToJSONFailed)?;
+        let new = serde_json::to_value(other).map_err(ConfigError::ConfigToJSONFailed)?;
+        let mut changes = Vec::new();
+        diff_values("", &old, &new, &mut changes);
+        Ok(changes)
+    }
+
+    pub fn to_json(&self) -> Result<String, ConfigError> {
+        serde_json::to_string_pretty(self).map_err(ConfigError::ConfigToJSONFailed)
+    }
+
+    // The inner `config::Config` isn't serialized, the returned config starts with an empty one.
+    pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        serde_json::from_str(json).map_err(ConfigError::ConfigToJSONFailed)
+    }
+}
+
+/// A setting that differs between two configs. Changes to the net and datastore sections only take effect after a
+/// restart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigChange {
+    pub path: String,
+    pub old: String,
+    pub new: String,
+    pub requires_restart: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -171,20 +367,91 @@ pub struct MemoryConfig {
 }
 
 impl DatastoreConfig {
-    // TODO: add default config
+    fn default_data_store_config() -> Self {
+        DatastoreConfig {
+            store: "badger".to_string(),
+            memory: MemoryConfig {
+                size: GiB.as_bytes(),
+            },
+            badger: BadgerConfig {
+                path: "data".to_string(),
+                value_log_file_size: GiB,
+            },
+            max_txn_retries: 5,
+        }
+    }
 
     fn validate(&self) -> Result<(), ConfigError> {
+        // 0 disables retries.
+        if !(0..=MAX_TXN_RETRIES).contains(&self.max_txn_retries) {
+            return Err(ConfigError::InvalidMaxTxnRetries(self.max_txn_retries));
+        }
+
         match self.store.as_str() {
-            "badger" | "memory" => Ok(()),
+            "badger" => self.validate_badger_path(),
+            "memory" if self.memory.size < MIN_MEMORY_STORE_SIZE.as_bytes() => Err(ConfigError::InvalidMemorySize(self.memory.size)),
+            "memory" => Ok(()),
             _ => Err(ConfigError::InvalidDatastoreType(self.store.clone())),
         }
     }
+
+    // Relative paths are resolved against the rootdir during preprocessing and can't be checked before.
+    // Only metadata is read here, validation must not write to the filesystem, see `check_badger_writable`.
+    fn validate_badger_path(&self) -> Result<(), ConfigError> {
+        let path = Path::new(&self.badger.path);
+        if !path.is_absolute() {
+            return Ok(());
+        }
+
+        let invalid = |reason: &str| ConfigError::InvalidDatastorePath(format!("{}: {}", path.display(), reason));
+        if path.exists() && !path.is_dir() {
+            return Err(invalid("not a directory"));
+        }
+
+        let parent = path.parent().ok_or_else(|| invalid("no parent directory"))?;
+        let metadata = fs::metadata(parent).map_err(|e| invalid(&format!("{}: {}", parent.display(), e)))?;
+        if !metadata.is_dir() {
+            return Err(invalid(&format!("{} is not a directory", parent.display())));
+        }
+        Ok(())
+    }
+
+    // The permission bits don't account for ownership, ACLs or read-only mounts, so a file is created instead. The
+    // probe goes into the badger directory when it exists and into its parent otherwise, both checked by `validate`.
+    fn check_badger_writable(&self) -> Result<(), ConfigError> {
+        if self.store != "badger" {
+            return Ok(());
+        }
+
+        let path = Path::new(&self.badger.path);
+        let dir = if path.is_dir() { path } else { path.parent().unwrap_or(path) };
+        let invalid = |e: io::Error| ConfigError::InvalidDatastorePath(format!("{} is not writable: {}", dir.display(), e));
+        let probe = dir.join(format!(".defradb-probe-{}", std::process::id()));
+        OpenOptions::new().write(true).create_new(true).open(&probe).map_err(invalid)?;
+        fs::remove_file(&probe).map_err(invalid)
+    }
+
+    pub fn badger_value_log_size_bytes(&self) -> u64 {
+        self.badger.value_log_file_size.as_bytes()
+    }
+
+    /// Opens the datastore selected by `store`. Clones of the returned store are handles to the same data.
+    /// The memory store holds at most `memory.size` bytes.
+    pub fn open(&self) -> Result<Box<dyn IterableTxnDatastore>, Box<dyn Error>> {
+        match self.store.as_str() {
+            // There is no badger binding yet, so only the memory store can be opened.
+            "badger" => Err(DatastoreError::from(BadgerError::Unavailable).into()),
+            "memory" => Ok(Box::new(MemoryStore::with_capacity(self.memory.size))),
+            _ => Err(ConfigError::InvalidDatastoreType(self.store.clone()).into()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct APIConfig {
     pub address: String,
     pub tls: bool,
+    #[serde(default, alias = "allowedorigins", deserialize_with = "deserialize_string_list")]
     pub allowed_origins: Vec<String>,
     pub pub_key_path: String,
     pub priv_key_path: String,
@@ -198,42 +465,126 @@ impl APIConfig {
             address: "localhost:9181".to_string(),
             tls: false,
             allowed_origins: vec![],
-            pub_key_path: "certs/server.key".to_string(),
-            priv_key_path: "certs/server.crt".to_string(),
+            pub_key_path: "certs/server.crt".to_string(),
+            priv_key_path: "certs/server.key".to_string(),
             email: DEFAULT_API_EMAIL.to_string(),
         }
     }
 
     fn validate(&self) -> Result<(), ConfigError> {
+        if self.tls {
+            self.validate_tls()?;
+        }
+
+        self.validate_email()?;
+
+        for origin in &self.allowed_origins {
+            if !Self::is_valid_origin(origin) {
+                return Err(ConfigError::InvalidAllowedOrigin(origin.clone()));
+            }
+        }
+
         if self.address.is_empty() {
             return Err(ConfigError::InvalidDatabaseURL);
         }
 
-        if self.address == "localhost" || self.address.parse::<SocketAddr>().is_ok() {
+        // A bare host without a port.
+        if self.address == "localhost" || self.address.parse::<IpAddr>().is_ok() {
             return Err(ConfigError::MissingPortNumber);
         }
 
+        // IPv6 hosts have to be bracketed, `[::1]:9181`, and are handled by the SocketAddr parsing below.
+        if self.address.contains(['[', ']']) {
+            if self.address.parse::<SocketAddr>().is_ok() {
+                return Ok(());
+            }
+            let bare_host = self.address.strip_prefix('[').and_then(|s| s.strip_suffix(']'));
+            if bare_host.is_some_and(|host| host.parse::<std::net::Ipv6Addr>().is_ok()) {
+                return Err(ConfigError::MissingPortNumber);
+            }
+            return Err(ConfigError::InvalidDatabaseURL);
+        }
+
+        // A domain name on its own is allowed, the port is then picked by the server (e.g. for automatic TLS).
         if Self::is_valid_domain_name(&self.address) {
             return Ok(());
         }
 
-        // Try parsing as "host:port"
-        if let Ok(addrs) = (&self.address[..], 0).to_socket_addrs() {
-            for addr in addrs {
-                if addr.ip().is_loopback() {
-                    return Ok(());
-                }
-                if !Self::is_valid_domain_name(&addr.ip().to_string()) {
-                    return Err(ConfigError::NoPortWithDomain);
-                }
-            }
-        } else {
+        // An IP address with a port. Port 0 is allowed and lets the OS pick one at bind time.
+        if self.address.parse::<SocketAddr>().is_ok() {
+            return Ok(());
+        }
+
+        // Otherwise it has to be "host:port", where only localhost is allowed as a named host.
+        let Some((host, port)) = self.address.rsplit_once(':') else {
+            return Err(ConfigError::InvalidDatabaseURL);
+        };
+        if port.parse::<u16>().is_err() {
             return Err(ConfigError::InvalidDatabaseURL);
         }
+        if host != "localhost" {
+            return Err(ConfigError::NoPortWithDomain);
+        }
 
         Ok(())
     }
 
+    fn validate_tls(&self) -> Result<(), ConfigError> {
+        for (key, path) in [("pubkeypath", &self.pub_key_path), ("privkeypath", &self.priv_key_path)] {
+            if path.is_empty() {
+                return Err(ConfigError::MissingTLSCertificate(format!("{} is not set", key)));
+            }
+
+            // Relative paths are resolved against the rootdir during preprocessing and can't be checked before.
+            let mut expanded = path.clone();
+            expand_home_dir(&mut expanded)?;
+            if Path::new(&expanded).is_absolute() && !Path::new(&expanded).is_file() {
+                return Err(ConfigError::MissingTLSCertificate(expanded));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_email(&self) -> Result<(), ConfigError> {
+        let parts: Vec<&str> = self.email.split('@').collect();
+        if parts.len() != 2 || parts[0].is_empty() || parts[1].is_empty() || !Self::is_valid_domain_name(parts[1]) {
+            return Err(ConfigError::InvalidEmail(self.email.clone()));
+        }
+
+        Ok(())
+    }
+
+    // An origin is either the `*` wildcard or `scheme://host[:port]`, optionally followed by a `/`.
+    fn is_valid_origin(origin: &str) -> bool {
+        if origin == "*" {
+            return true;
+        }
+
+        let Some((scheme, rest)) = origin.split_once("://") else {
+            return false;
+        };
+        if scheme.is_empty() || !scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) {
+            return false;
+        }
+
+        let rest = rest.strip_suffix('/').unwrap_or(rest);
+        let host = match rest.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => host,
+            Some(_) => return false,
+            None => rest,
+        };
+
+        !host.is_empty() && Self::is_valid_domain_name(&host.to_lowercase())
+    }
+
+    pub fn normalized_origins(&self) -> Vec<String> {
+        self.allowed_origins
+            .iter()
+            .map(|origin| origin.trim_end_matches('/').to_lowercase())
+            .collect()
+    }
+
     fn is_valid_domain_name(domain: &str) -> bool {
         let config = idna::Config::default()
             .transitional_processing(false)
@@ -245,6 +596,33 @@ impl APIConfig {
         }
     }
 
+    /// Returns whether the API only listens on the loopback interface.
+    pub fn is_loopback(&self) -> bool {
+        let host = match self.address.parse::<SocketAddr>() {
+            Ok(addr) => return addr.ip().is_loopback(),
+            Err(_) => self.address.rsplit_once(':').map_or(self.address.as_str(), |(host, _)| host),
+        };
+        host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    }
+
+    /// Resolves the address to the socket address the server binds. A host name may resolve to several addresses,
+    /// IPv4 ones are preferred unless the address is a bracketed IPv6 one.
+    pub fn bind_addr(&self) -> Result<SocketAddr, ConfigError> {
+        let addrs: Vec<SocketAddr> = self
+            .address
+            .to_socket_addrs()
+            .map_err(|_| ConfigError::InvalidDatabaseURL)?
+            .collect();
+        let want_ipv6 = self.address.starts_with('[');
+        addrs
+            .iter()
+            .find(|addr| addr.is_ipv6() == want_ipv6)
+            .or(addrs.first())
+            .copied()
+            .ok_or(ConfigError::InvalidDatabaseURL)
+    }
+
+    /// Port 0 is kept as is, the effective port is resolved when the server binds.
     pub fn address_to_url(&self) -> String {
         if self.tls {
             format!("https://{}", self.address)
@@ -256,9 +634,14 @@ impl APIConfig {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetConfig {
-    pub p2p_address: String,
+    /// The listen addresses, a single multiaddr or a list of them, e.g. a tcp and a quic one.
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub p2p_address: Vec<String>,
     pub p2p_disabled: bool,
     pub peers: String,
+    /// Whether the peers set through the environment are added to the ones of the config file instead of
+    /// replacing them, see `Config::effective_peers`.
+    pub union_env_peers: bool,
     pub pub_sub_enabled: bool,
     pub relay_enabled: bool,
 }
@@ -266,26 +649,104 @@ pub struct NetConfig {
 impl NetConfig {
 
     fn default_net_config() -> Self {
-        return NetConfig {
-            p2p_address: "/ip4/0.0.0.0/tcp/9171".to_string(),
+        NetConfig {
+            p2p_address: vec!["/ip4/0.0.0.0/tcp/9171".to_string()],
             p2p_disabled: false,
             peers: "".to_string(),
+            union_env_peers: false,
             pub_sub_enabled: true,
             relay_enabled: false,
         }
     }
     fn validate(&self) -> Result<(), ConfigError> {
-        self.p2p_address.parse::<Multiaddr>().map_err(|err| ConfigError::InvalidP2PAddress(err.to_string(), self.p2p_address.clone()))?;
+        if self.p2p_disabled {
+            if self.pub_sub_enabled {
+                return Err(ConfigError::ContradictoryNetConfig("pubsub is enabled but p2p is disabled".to_string()));
+            }
+            if self.relay_enabled {
+                return Err(ConfigError::ContradictoryNetConfig("relay is enabled but p2p is disabled".to_string()));
+            }
+            return Ok(());
+        }
+
+        if self.p2p_address.is_empty() {
+            return Err(ConfigError::ContradictoryNetConfig("p2p is enabled but no p2p address is set".to_string()));
+        }
+        self.p2p_multiaddrs()?;
+
+        self.parse_peers()?;
+
+        Ok(())
+    }
+
+    /// Returns the first p2p listen address, or `None` when p2p is disabled or no address is set.
+    /// A `/tcp/0` address lets the OS pick the port, the effective port is only known once bound.
+    pub fn p2p_multiaddr(&self) -> Result<Option<Multiaddr>, ConfigError> {
+        if self.p2p_disabled {
+            return Ok(None);
+        }
+        Ok(self.p2p_multiaddrs()?.into_iter().next())
+    }
+
+    /// Returns all p2p listen addresses.
+    pub fn p2p_multiaddrs(&self) -> Result<Vec<Multiaddr>, ConfigError> {
+        self.p2p_address
+            .iter()
+            .map(|addr| addr.parse::<Multiaddr>().map_err(|err| ConfigError::InvalidP2PAddress(err, addr.clone())))
+            .collect()
+    }
 
-        if !self.peers.is_empty() {
-            let peers: Vec<&str> = self.peers.split(',').collect();
-            for addr in &peers {
-                addr.parse::<Multiaddr>().map_err(|err| ConfigError::InvalidBootstrapPeers(err.to_string(), peers.clone().iter().map(|x| (**x).to_string()).collect::<Vec<_>>().join(", ")))?;
+    /// Lets the OS assign the p2p ports and drops the peers, so nodes started side by side don't collide or connect.
+    /// Both tcp and udp (e.g. quic) ports are set to 0, an address without either is an `InvalidP2PAddress`.
+    pub fn with_random_ports(&mut self) -> Result<(), ConfigError> {
+        let mut addresses = Vec::with_capacity(self.p2p_address.len());
+        for addr in self.p2p_multiaddrs()? {
+            let mut has_port = false;
+            let random: Multiaddr = addr
+                .iter()
+                .map(|protocol| match protocol {
+                    Protocol::Tcp(_) => {
+                        has_port = true;
+                        Protocol::Tcp(0)
+                    }
+                    Protocol::Udp(_) => {
+                        has_port = true;
+                        Protocol::Udp(0)
+                    }
+                    protocol => protocol,
+                })
+                .collect();
+            if !has_port {
+                let err = multiaddr::Error::ParsingError("no tcp or udp port".into());
+                return Err(ConfigError::InvalidP2PAddress(err, addr.to_string()));
             }
+            addresses.push(random.to_string());
         }
 
+        self.p2p_address = addresses;
+        self.peers = String::new();
+        Ok(())
+    }
+
+    /// Appends the peers listed in `path` to `peers`, one multiaddr per line.
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn load_peers_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let contents = fs::read_to_string(path)?;
+        let mut peers: Vec<String> = self.parse_peers()?.iter().map(Multiaddr::to_string).collect();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let addr = line.parse::<Multiaddr>().map_err(|err| ConfigError::InvalidBootstrapPeers(err, line.to_string()))?;
+            peers.push(addr.to_string());
+        }
+        self.peers = peers.join(",");
         Ok(())
     }
+
+    pub fn parse_peers(&self) -> Result<Vec<Multiaddr>, ConfigError> {
+        parse_peer_list(&self.peers)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -297,6 +758,7 @@ pub struct LoggingConfig {
     pub caller: bool,
     pub no_color: bool,
     pub logger: String,
+    #[serde(default)]
     pub named_overrides: HashMap<String, NamedLoggingConfig>,
 }
 
@@ -321,142 +783,197 @@ impl LoggingConfig {
     }
 
     fn validate(&self) -> Result<(), ConfigError> {
-        fn valid_level(level: &str) -> bool {
-            match level {
-                LOG_LEVEL_DEBUG | LOG_LEVEL_INFO | LOG_LEVEL_ERROR | LOG_LEVEL_FATAL => true,
-                _ => false,
-            }
-        }
-
-        fn ensure_unique_keys(kvs: &Vec<HashMap<&str, &str>>) -> Result<(), ConfigError> {
-            let mut keys = HashSet::new();
-            for kv in kvs {
-                for k in kv.keys() {
-                    if keys.contains(k) {
-                        return Err(ConfigError::DuplicateLoggerName(k.to_string()))
-                    }
-                    keys.insert(k);
-                }
-            }
-            Ok(())
-        }
-
-        let valid_levels = ["logLevelDebug", "logLevelInfo", "logLevelError", "logLevelFatal"];
+        self.format.parse::<LogFormat>()?;
+        self.output.parse::<LogOutput>()?.validate()?;
 
         let parts: Vec<&str> = self.level.split(',').collect();
+        parts[0].parse::<LogLevel>()?;
 
-        if !parts.is_empty() && !valid_levels.contains(&parts[0]) {
-            return Err(ConfigError::InvalidLogLevel(parts[0].to_string()));
+        let mut keys = Vec::new();
+        for kv in &parts[1..] {
+            let (key, value) = parse_kv(kv).map_err(|_| ConfigError::NotProvidedAsKV(kv.to_string()))?;
+            validate_logger_name(&key)?;
+            value.parse::<LogLevel>()?;
+            keys.push(key);
         }
+        ensure_unique_keys(keys.iter().map(String::as_str))?;
 
-        let mut kvs: Vec<HashMap<&str, &str>> = Vec::new();
-        for kv in &parts[1..] {
-            let parsed_kv: Vec<&str> = kv.split('=').collect();
-            if parsed_kv.len() != 2 || parsed_kv[0].is_empty() || parsed_kv[1].is_empty() {
-                return Err(ConfigError::NotProvidedAsKV(kv.to_string()));
-            }
+        parse_logger_overrides(&self.logger)?;
 
-            let mut new_kv = HashMap::new();
-            new_kv.insert(parsed_kv[0], parsed_kv[1]);
-            kvs.push(new_kv);
+        for named in self.named_overrides.values() {
+            named.validate()?;
         }
+        Ok(())
+    }
 
-        if !self.logger.is_empty() {
-            let named_configs: Vec<&str> = self.logger.split(';').collect();
-            for config in &named_configs {
-                let parts: Vec<&str> = config.split(',').collect();
-                if parts.len() < 2 {
-                    return Err(ConfigError::InvalidLoggerConfig("unexpected format (expected: `module,key=value;module,key=value;...`".to_string()).into());
-                }
-                if parts[0].is_empty() {
-                    return Err(ConfigError::InvalidLoggerName("".to_string()).into());
-                }
-                for pair in &parts[1..] {
-                    let parsed_kv: Vec<&str> = pair.split('=').collect();
-                    if parsed_kv.len() != 2 || parsed_kv[0].is_empty() || parsed_kv[1].is_empty() {
-                        return Err(ConfigError::NotProvidedAsKV(pair.to_string()).into());
-                    }
-                    match parsed_kv[0] {
-                        "format" | "output" | "nocolor" | "stacktrace" | "caller" => {}
-                        "level" if valid_levels.contains(&parsed_kv[1]) => {}
-                        _ => return Err(ConfigError::UnknownLoggerParameter(parsed_kv[0].to_string()).into()),
-                    }
-                }
-            }
+    fn load(&mut self) -> Result<(), ConfigError> {
+        self.resolve()?;
+
+        let c = self.to_logger_config()?;
+        // Opening the outputs creates the log files, so an unwritable path fails the load rather than the first log.
+        c.output.open()?;
+        for override_cfg in c.overrides_by_logger_name.values() {
+            override_cfg.output.open()?;
         }
+        log::set_max_level(c.max_level());
         Ok(())
     }
 
-    fn load(&mut self) -> Result<(), ConfigError> {
+    // Resolves relative file outputs against `rootdir`, including the outputs in the logger setting and the named
+    // overrides. Values that aren't valid outputs are left for validation to report.
+    fn resolve_outputs(&mut self, rootdir: &Path) {
+        let resolve = |output: &str| match output.parse::<LogOutput>() {
+            Ok(LogOutput::File(path)) if path.is_relative() => rootdir.join(path).to_string_lossy().into_owned(),
+            _ => output.to_string(),
+        };
+
+        self.output = resolve(&self.output);
+        self.logger = self
+            .logger
+            .split(';')
+            .map(|config| {
+                config
+                    .split(',')
+                    .map(|pair| match pair.split_once('=') {
+                        Some((key, value)) if key.eq_ignore_ascii_case("output") => format!("{}={}", key, resolve(value)),
+                        _ => pair.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join(";");
+        for named in self.named_overrides.values_mut() {
+            named.logging_config.resolve_outputs(rootdir);
+        }
+    }
+
+    // Folds the module levels and the logger setting into the named overrides.
+    // Levels set through `RUST_LOG` take precedence over `DEFRA_LOG_LEVEL`, which takes precedence over the file.
+    fn resolve(&mut self) -> Result<(), ConfigError> {
+        let (rust_log_level, rust_log_modules) = match std::env::var(RUST_LOG_ENV) {
+            Ok(directives) => parse_rust_log(&directives),
+            Err(_) => (None, Vec::new()),
+        };
+
         // load loglevel
         let parts_copy = self.level.clone();
         let parts: Vec<&str> = parts_copy.split(',').collect();
         if !parts.is_empty() {
             self.level = parts[0].to_string();
         }
+        if let Some(level) = rust_log_level {
+            self.level = level.as_str().to_string();
+        }
         if parts.len() > 1 {
             for kv in &parts[1..] {
-                let parsed_kv: Vec<&str> = kv.split('=').collect();
-                if parsed_kv.len() != 2 {
-                    return Err(ConfigError::InvalidLogLevel(kv.to_string()).into());
-                }
-                match self.get_or_create_named_logger(parsed_kv[0]) {
-                    Ok(c) => c.logging_config.level = parsed_kv[1].to_string(),
-                    Err(e) => return Err(ConfigError::CouldNotObtainLoggerConfig(e.to_string(), parsed_kv[0].to_string()).into()),
+                let (key, value) = parse_kv(kv).map_err(|_| ConfigError::NotProvidedAsKV(kv.to_string()))?;
+                match self.get_or_create_named_logger(&key) {
+                    Ok(c) => c.logging_config.level = value,
+                    Err(e) => return Err(ConfigError::CouldNotObtainLoggerConfig(e.to_string(), key)),
                 }
             }
         }
 
         // load logger
-        if !self.logger.is_empty() {
-            let logger_copy = self.logger.clone();
-            let s: Vec<&str> = logger_copy.split(';').collect();
-            for v in s {
-                let vs: Vec<&str> = v.split(',').collect();
-                let mut override_logger = self.get_or_create_named_logger(vs[0])?;
-                override_logger.name = vs[0].to_string();
-                for v in &vs[1..] {
-                    let parsed_kv: Vec<&str> = v.split('=').collect();
-                    if parsed_kv.len() != 2 {
-                        return Err(ConfigError::NotProvidedAsKV(v.to_string()).into());
-                    }
-                    match parsed_kv[0].to_lowercase().as_str() {
-                        "level" => override_logger.logging_config.level = parsed_kv[1].to_string(),
-                        "format" => override_logger.logging_config.format = parsed_kv[1].to_string(),
-                        "output" => override_logger.logging_config.output = parsed_kv[1].to_string(),
-                        "stacktrace" => match parsed_kv[1].parse::<bool>() {
-                            Ok(val) => override_logger.logging_config.stacktrace = val,
-                            Err(_) => return Err(ConfigError::CouldNotParseType("bool".to_string()).into()),
-                        },
-                        "nocolor" => match parsed_kv[1].parse::<bool>() {
-                            Ok(val) => override_logger.logging_config.no_color = val,
-                            Err(_) => return Err(ConfigError::CouldNotParseType("bool".to_string()).into()),
-                        },
-                        "caller" => match parsed_kv[1].parse::<bool>() {
-                            Ok(val) => override_logger.logging_config.caller = val,
-                            Err(_) => return Err(ConfigError::CouldNotParseType("bool".to_string()).into()),
-                        },
-                        _ => return Err(ConfigError::UnknownLoggerParameter(parsed_kv[0].to_string()).into()),
-                    }
-                }
+        for logger_override in parse_logger_overrides(&self.logger)? {
+            let named = self.get_or_create_named_logger(&logger_override.name)?;
+            let cfg = &mut named.logging_config;
+            if let Some(level) = logger_override.level {
+                cfg.level = level.as_str().to_string();
+            }
+            if let Some(format) = logger_override.format {
+                cfg.format = format.as_str().to_string();
+            }
+            if let Some(output) = logger_override.output {
+                cfg.output = output.to_string();
+            }
+            if let Some(stacktrace) = logger_override.stacktrace {
+                cfg.stacktrace = stacktrace;
+            }
+            if let Some(no_color) = logger_override.no_color {
+                cfg.no_color = no_color;
+            }
+            if let Some(caller) = logger_override.caller {
+                cfg.caller = caller;
             }
         }
 
-        // TODO: Implmenet corresponding to_logger_config() method
-        // let c = self.to_logger_config()?;
+        for (module, level) in rust_log_modules {
+            self.get_or_create_named_logger(&module)?.logging_config.level = level.as_str().to_string();
+        }
+        Ok(())
+    }
 
-        // TODO: set logging config
-        // logging::set_config(c);
+    // The named overrides aren't part of the config file, so they are written back into the `logger` setting.
+    fn normalize(&mut self) -> Result<(), ConfigError> {
+        self.resolve()?;
+
+        let mut names: Vec<&String> = self.named_overrides.keys().collect();
+        names.sort();
+        self.logger = names
+            .into_iter()
+            .map(|name| {
+                let cfg = &self.named_overrides[name].logging_config;
+                format!(
+                    "{},level={},format={},output={},stacktrace={},nocolor={},caller={}",
+                    name, cfg.level, cfg.format, cfg.output, cfg.stacktrace, cfg.no_color, cfg.caller
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(";");
         Ok(())
     }
 
+    /// Returns the global log level as a filter. Module levels in `level` are ignored and an unknown level maps to
+    /// `Info`, validation rejects those before a config is loaded.
+    pub fn log_level_filter(&self) -> LevelFilter {
+        let global = self.level.split(',').next().unwrap_or_default();
+        global.parse::<LogLevel>().map_or(LevelFilter::Info, LogLevel::to_level_filter)
+    }
+
+    pub fn to_logger_config(&self) -> Result<LoggerConfig, ConfigError> {
+        let mut c = self.to_base_logger_config()?;
+
+        // Named overrides are flattened into a single level of per-module configs.
+        for (name, named_cfg) in &self.named_overrides {
+            let override_cfg = named_cfg.logging_config.to_base_logger_config()?;
+            c.overrides_by_logger_name.insert(name.clone(), override_cfg);
+        }
+
+        Ok(c)
+    }
+
+    fn to_base_logger_config(&self) -> Result<LoggerConfig, ConfigError> {
+        let output: LogOutput = self.output.parse()?;
+        Ok(LoggerConfig {
+            level: self.level.parse::<LogLevel>()?.to_level_filter(),
+            format: self.format.parse()?,
+            no_color: effective_no_color(self.no_color, std::env::var_os(NO_COLOR_ENV), output.is_terminal()),
+            output,
+            caller: self.caller,
+            stacktrace: self.stacktrace,
+            overrides_by_logger_name: HashMap::new(),
+        })
+    }
+
     fn get_or_create_named_logger(&mut self, name: &str) -> Result<&mut NamedLoggingConfig, ConfigError> {
         // Check if the named logger exists.
         if !self.named_overrides.contains_key(name) {
             // If doesn't exist, create a new named logger
+            // Only the scalar fields are inherited, nesting the overrides would grow the config with every new logger.
             let named_cfg = NamedLoggingConfig {
                 name: name.to_string(),
-                logging_config: self.clone(),
+                logging_config: LoggingConfig {
+                    level: self.level.clone(),
+                    stacktrace: self.stacktrace,
+                    format: self.format.clone(),
+                    output: self.output.clone(),
+                    caller: self.caller,
+                    no_color: self.no_color,
+                    logger: "".to_string(),
+                    named_overrides: HashMap::new(),
+                },
             };
             self.named_overrides.insert(name.to_string(), named_cfg);
         }
@@ -466,12 +983,1422 @@ impl LoggingConfig {
     }
 }
 
-impl NamedLoggingConfig {
-    fn validate(&self) -> Result<(), ConfigError> {
-        self.logging_config.validate()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl LogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => LOG_LEVEL_TRACE,
+            LogLevel::Debug => LOG_LEVEL_DEBUG,
+            LogLevel::Info => LOG_LEVEL_INFO,
+            LogLevel::Warn => LOG_LEVEL_WARN,
+            LogLevel::Error => LOG_LEVEL_ERROR,
+            LogLevel::Fatal => LOG_LEVEL_FATAL,
+        }
+    }
+
+    pub fn to_level_filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Trace => LevelFilter::Trace,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Warn => LevelFilter::Warn,
+            // The log crate has no fatal level, error is the most severe one.
+            LogLevel::Error | LogLevel::Fatal => LevelFilter::Error,
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, ConfigError> {
+        match s {
+            LOG_LEVEL_TRACE => Ok(LogLevel::Trace),
+            LOG_LEVEL_DEBUG => Ok(LogLevel::Debug),
+            LOG_LEVEL_INFO => Ok(LogLevel::Info),
+            LOG_LEVEL_WARN => Ok(LogLevel::Warn),
+            LOG_LEVEL_ERROR => Ok(LogLevel::Error),
+            LOG_LEVEL_FATAL => Ok(LogLevel::Fatal),
+            _ => Err(ConfigError::InvalidLogLevel(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Csv,
+    Json,
+    Text,
+}
+
+impl LogFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogFormat::Csv => "csv",
+            LogFormat::Json => "json",
+            LogFormat::Text => "text",
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, ConfigError> {
+        match s {
+            "csv" => Ok(LogFormat::Csv),
+            "json" => Ok(LogFormat::Json),
+            "text" => Ok(LogFormat::Text),
+            _ => Err(ConfigError::InvalidLogFormat(s.to_string())),
+        }
     }
 }
 
+/// Where the logs are written to, `stderr`, `stdout` or the path of a file. A file path must contain a separator, e.g.
+/// `./defradb.log`, so a misspelled stream isn't mistaken for a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogOutput {
+    Stderr,
+    Stdout,
+    File(PathBuf),
+}
+
+impl LogOutput {
+    /// Opens the output for writing. Log files are appended to, and created along with their parent directories
+    /// if they don't exist.
+    pub fn open(&self) -> Result<Box<dyn io::Write + Send>, ConfigError> {
+        match self {
+            LogOutput::Stderr => Ok(Box::new(io::stderr())),
+            LogOutput::Stdout => Ok(Box::new(io::stdout())),
+            LogOutput::File(path) => {
+                let unwritable = |e: io::Error| ConfigError::InvalidLoggerConfig(format!("cannot write to {}: {}", path.display(), e));
+                if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                    fs::create_dir_all(parent).map_err(unwritable)?;
+                }
+                let file = OpenOptions::new().create(true).append(true).open(path).map_err(unwritable)?;
+                Ok(Box::new(file))
+            }
+        }
+    }
 
+    /// Returns whether the output is a terminal, log files never are.
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            LogOutput::Stderr => io::stderr().is_terminal(),
+            LogOutput::Stdout => io::stdout().is_terminal(),
+            LogOutput::File(_) => false,
+        }
+    }
 
+    // Checks what can be checked without creating the file.
+    fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            LogOutput::File(path) if path.is_dir() => {
+                Err(ConfigError::InvalidLoggerConfig(format!("output {} is a directory", path.display())))
+            }
+            _ => Ok(()),
+        }
+    }
+}
 
+impl fmt::Display for LogOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogOutput::Stderr => f.write_str("stderr"),
+            LogOutput::Stdout => f.write_str("stdout"),
+            LogOutput::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for LogOutput {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, ConfigError> {
+        match s {
+            "" => Err(ConfigError::InvalidLoggerConfig("output cannot be empty".to_string())),
+            "stderr" => Ok(LogOutput::Stderr),
+            "stdout" => Ok(LogOutput::Stdout),
+            path if path.contains('/') || path.contains(std::path::MAIN_SEPARATOR) => Ok(LogOutput::File(PathBuf::from(path))),
+            other => Err(ConfigError::InvalidLoggerConfig(format!(
+                "output {} is neither stderr, stdout nor a file path, use ./{} for a file",
+                other, other
+            ))),
+        }
+    }
+}
+
+/// A single module entry of the `logger` setting, e.g. `net,level=debug,nocolor=true`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoggerOverride {
+    pub name: String,
+    pub level: Option<LogLevel>,
+    pub format: Option<LogFormat>,
+    pub output: Option<LogOutput>,
+    pub stacktrace: Option<bool>,
+    pub no_color: Option<bool>,
+    pub caller: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggerConfig {
+    pub level: LevelFilter,
+    pub format: LogFormat,
+    pub output: LogOutput,
+    pub caller: bool,
+    pub no_color: bool,
+    pub stacktrace: bool,
+    pub overrides_by_logger_name: HashMap<String, LoggerConfig>,
+}
+
+impl LoggerConfig {
+    // The most verbose level across the global config and all overrides, so that
+    // per-module levels aren't filtered out by the global maximum.
+    pub fn max_level(&self) -> LevelFilter {
+        self.overrides_by_logger_name
+            .values()
+            .map(|c| c.level)
+            .fold(self.level, Ord::max)
+    }
+}
+
+impl NamedLoggingConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        validate_logger_name(&self.name)?;
+        self.logging_config.validate()
+    }
+}
+
+// Named loggers are lowercase letters only, e.g. `net`.
+fn validate_logger_name(name: &str) -> Result<(), ConfigError> {
+    if name.is_empty() || !is_lowercase_alpha(name) {
+        return Err(ConfigError::InvalidNamedLoggerName(name.to_string()));
+    }
+    Ok(())
+}
+
+fn ensure_unique_keys<'a>(keys: impl IntoIterator<Item = &'a str>) -> Result<(), ConfigError> {
+    let mut seen = HashSet::new();
+    for k in keys {
+        if !seen.insert(k) {
+            return Err(ConfigError::DuplicateLoggerName(k.to_string()));
+        }
+    }
+    Ok(())
+}
+
+// Parses the `logger` setting, `module,key=value;module,key=value;...`. Keys are case-insensitive.
+fn parse_logger_overrides(s: &str) -> Result<Vec<LoggerOverride>, ConfigError> {
+    if s.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let named_configs: Vec<&str> = s.split(';').collect();
+    ensure_unique_keys(named_configs.iter().map(|config| config.split(',').next().unwrap_or_default()))?;
+
+    let parse_bool = |v: &str| v.parse::<bool>().map_err(|_| ConfigError::CouldNotParseType("bool".to_string()));
+    let mut overrides = Vec::with_capacity(named_configs.len());
+    for config in named_configs {
+        let parts: Vec<&str> = config.split(',').collect();
+        if parts.len() < 2 {
+            return Err(ConfigError::InvalidLoggerConfig("unexpected format (expected: `module,key=value;module,key=value;...`".to_string()));
+        }
+        validate_logger_name(parts[0])?;
+
+        let mut logger_override = LoggerOverride { name: parts[0].to_string(), ..Default::default() };
+        for pair in &parts[1..] {
+            let (key, value) = parse_kv(pair).map_err(|_| ConfigError::NotProvidedAsKV(pair.to_string()))?;
+            let value = value.as_str();
+            match key.to_lowercase().as_str() {
+                "level" => logger_override.level = Some(value.parse()?),
+                "format" => logger_override.format = Some(value.parse()?),
+                "output" => logger_override.output = Some(value.parse()?),
+                "stacktrace" => logger_override.stacktrace = Some(parse_bool(value)?),
+                "nocolor" => logger_override.no_color = Some(parse_bool(value)?),
+                "caller" => logger_override.caller = Some(parse_bool(value)?),
+                _ => return Err(ConfigError::UnknownLoggerParameter(key)),
+            }
+        }
+        overrides.push(logger_override);
+    }
+    Ok(overrides)
+}
+
+// Walks both values in lockstep, objects are compared key by key and anything else as a whole.
+fn diff_values(path: &str, old: &serde_json::Value, new: &serde_json::Value, changes: &mut Vec<ConfigChange>) {
+    use serde_json::Value as Json;
+
+    if old == new {
+        return;
+    }
+    if let (Json::Object(old), Json::Object(new)) = (old, new) {
+        let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for key in keys {
+            let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+            diff_values(&path, old.get(key).unwrap_or(&Json::Null), new.get(key).unwrap_or(&Json::Null), changes);
+        }
+        return;
+    }
+
+    let to_string = |value: &Json| match value {
+        Json::Null => String::new(),
+        Json::String(s) => s.clone(),
+        value => value.to_string(),
+    };
+    changes.push(ConfigChange {
+        path: path.to_string(),
+        old: to_string(old),
+        new: to_string(new),
+        requires_restart: path.starts_with("net.") || path.starts_with("datastore."),
+    });
+}
+
+// Parses a comma separated list of multiaddrs, empty entries are skipped.
+fn parse_peer_list(peers: &str) -> Result<Vec<Multiaddr>, ConfigError> {
+    peers
+        .split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(|addr| addr.parse::<Multiaddr>().map_err(|err| ConfigError::InvalidBootstrapPeers(err, peers.to_string())))
+        .collect()
+}
+
+// Colors are only used on a terminal, and `NO_COLOR` turns them off regardless of the config (see no-color.org).
+// Setting `no_color` to false doesn't force colors into a pipe or a file.
+fn effective_no_color(no_color: bool, no_color_env: Option<OsString>, is_terminal: bool) -> bool {
+    no_color || no_color_env.is_some_and(|value| !value.is_empty()) || !is_terminal
+}
+
+// Parses `RUST_LOG` directives the way env_logger does, e.g. `net=debug,info`. A bare level sets the global level and
+// a bare module enables all of its logs. Directives that can't be expressed here are skipped with a warning: `off`,
+// the `/filter` suffixes and modules that aren't valid logger names, like `defradb_rs` or `crate::net`.
+fn parse_rust_log(directives: &str) -> (Option<LogLevel>, Vec<(String, LogLevel)>) {
+    let directives = directives.split_once('/').map_or(directives, |(directives, _)| directives);
+    let parse_level = |level: &str| level.to_lowercase().parse::<LogLevel>().ok();
+
+    let mut global = None;
+    let mut modules = Vec::new();
+    for directive in directives.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let (module, level) = match directive.split_once('=') {
+            Some((module, level)) => match parse_level(level) {
+                Some(level) => (module, level),
+                None => {
+                    log::warn!("ignoring {} directive {}", RUST_LOG_ENV, directive);
+                    continue;
+                }
+            },
+            None => match parse_level(directive) {
+                Some(level) => {
+                    global = Some(level);
+                    continue;
+                }
+                None if directive.eq_ignore_ascii_case("off") => {
+                    log::warn!("ignoring {} directive {}", RUST_LOG_ENV, directive);
+                    continue;
+                }
+                None => (directive, LogLevel::Trace),
+            },
+        };
+
+        match validate_logger_name(module) {
+            Ok(()) => modules.push((module.to_string(), level)),
+            Err(e) => log::warn!("ignoring {} directive {}: {}", RUST_LOG_ENV, directive, e),
+        }
+    }
+    (global, modules)
+}
+
+// Maps the errors of the config crate's merge and set_default calls, both re-read every source merged so far.
+fn source_error(e: config::ConfigError) -> ConfigError {
+    match e {
+        config::ConfigError::FileParse { .. } => parse_error_location(e),
+        e => ConfigError::ReadingConfigFile(e),
+    }
+}
+
+// Surfaces the line and column of YAML syntax errors, other parse errors don't carry a location.
+fn parse_error_location(e: config::ConfigError) -> ConfigError {
+    if let config::ConfigError::FileParse { cause, .. } = &e {
+        if let Some(scan_error) = cause.downcast_ref::<ScanError>() {
+            let marker = scan_error.marker();
+            let msg = scan_error.to_string();
+            // The message is suffixed with the location, which is already part of the variant.
+            let msg = msg.rsplit_once(" at line ").map_or(msg.as_str(), |(msg, _)| msg);
+            return ConfigError::ParseAt { line: marker.line(), col: marker.col() + 1, msg: msg.to_string() };
+        }
+    }
+    ConfigError::LoadingConfig(e)
+}
+
+// The keys are lowercase, environment variables are lowercased by the config crate.
+fn get_section<T: DeserializeOwned>(config: &config::Config, name: &str) -> Result<T, ConfigError> {
+    config.get(&name.to_lowercase()).map_err(ConfigError::LoadingConfig)
+}
+
+// Renders a value of the config template as JSON, so strings are quoted and lists stay lists.
+handlebars_helper!(yaml_helper: |value: Json| serde_json::to_string(value).unwrap_or_default());
+
+// The environment variables can set every key that has a default.
+fn env_source() -> EnvSource {
+    let defaults = serde_json::json!({
+        "datastore": DatastoreConfig::default_data_store_config(),
+        "api": APIConfig::default_api_config(),
+        "net": NetConfig::default_net_config(),
+        "log": LoggingConfig::default_log_config(),
+        "rootdir": "",
+    });
+    EnvSource::new(DEFRA_ENV_PREFIX, leaf_keys(&defaults))
+}
+
+// Converts a config section into a value that can be registered as a default.
+fn to_config_value<T: Serialize>(section: &T) -> Result<Value, ConfigError> {
+    Ok(config::Config::try_from(section).map_err(ConfigError::Config)?.cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+    use crate::config::test_utils::env_lock;
+
+    #[test]
+    fn io_failures_keep_their_source() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        cfg.rootdir = dir.path().join("missing").to_string_lossy().into_owned();
+
+        let err = cfg.write_config_file(false).unwrap_err();
+        assert!(matches!(err, ConfigError::Io(_)));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn reloading_a_malformed_file_reports_its_location() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.write_config_file(false).unwrap();
+        cfg.load_with_rootdir(true).unwrap();
+
+        fs::write(cfg.config_file_path(), "api:\n  address: localhost:9181\n  tls: [\n").unwrap();
+        let err = cfg.load_with_rootdir(true).unwrap_err();
+        assert!(matches!(err, ConfigError::ParseAt { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn env_vars_override_multi_word_keys() {
+        let mut env = env_lock();
+        env.set("DEFRA_API_ADDRESS", "0.0.0.0:9182");
+        env.set("DEFRA_API_PUB_KEY_PATH", "certs/other.crt");
+        env.set("DEFRA_LOG_LEVEL", "debug");
+        env.set("DEFRA_NET_P2P_DISABLED", "true");
+        env.set("DEFRA_NET_PUB_SUB_ENABLED", "false");
+        env.set("DEFRA_NET_P2P_ADDRESS", "/ip4/127.0.0.1/tcp/9172,/ip4/127.0.0.1/tcp/9173");
+        env.set("DEFRA_DATASTORE_MAX_TXN_RETRIES", "9");
+        env.set("DEFRA_DATASTORE_BADGER_PATH", "other");
+
+        let cfg = Config::default_config().unwrap();
+        assert_eq!(cfg.api.address, "0.0.0.0:9182");
+        assert_eq!(cfg.api.pub_key_path, "certs/other.crt");
+        assert_eq!(cfg.log.level, "debug");
+        assert!(cfg.net.p2p_disabled);
+        assert!(!cfg.net.pub_sub_enabled);
+        assert_eq!(cfg.net.p2p_address, vec!["/ip4/127.0.0.1/tcp/9172", "/ip4/127.0.0.1/tcp/9173"]);
+        assert_eq!(cfg.datastore.max_txn_retries, 9);
+        assert_eq!(cfg.datastore.badger.path, "other");
+    }
+
+    #[test]
+    fn env_vars_override_the_config_file() {
+        let mut env = env_lock();
+        let (cfg, _dir) = Config::clone_for_test();
+        cfg.write_config_file(false).unwrap();
+
+        env.set("DEFRA_NET_RELAY_ENABLED", "true");
+        let mut cfg = cfg.clone();
+        cfg.merge_file(Path::new(&cfg.config_file_path()), Some(FileFormat::Yaml)).unwrap();
+        assert!(cfg.net.relay_enabled);
+    }
+
+    #[test]
+    fn from_env_only_ignores_the_config_file() {
+        let mut env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        cfg.api.address = "localhost:1111".to_string();
+        cfg.write_config_file(false).unwrap();
+
+        env.set("DEFRA_ROOTDIR", &dir.path().to_string_lossy());
+        env.set("DEFRA_DATASTORE_STORE", "memory");
+        env.set("DEFRA_NET_P2P_DISABLED", "true");
+        env.set("DEFRA_NET_PUB_SUB_ENABLED", "false");
+        env.set("DEFRA_DATASTORE_MAX_TXN_RETRIES", "3");
+
+        let cfg = Config::from_env_only().unwrap();
+        assert_eq!(cfg.rootdir, dir.path().canonicalize().unwrap().to_string_lossy());
+        assert_eq!(cfg.api.address, "localhost:9181");
+        assert_eq!(cfg.datastore.store, "memory");
+        assert!(cfg.net.p2p_disabled && !cfg.net.pub_sub_enabled);
+        assert_eq!(cfg.datastore.max_txn_retries, 3);
+    }
+
+    #[test]
+    fn tls_requires_both_key_paths() {
+        let mut api = APIConfig::default_api_config();
+        api.tls = true;
+        api.pub_key_path = String::new();
+        assert!(matches!(api.validate(), Err(ConfigError::MissingTLSCertificate(_))));
+
+        api.pub_key_path = "/nonexistent/server.crt".to_string();
+        api.priv_key_path = "/nonexistent/server.key".to_string();
+        assert!(matches!(api.validate(), Err(ConfigError::MissingTLSCertificate(path)) if path == "/nonexistent/server.crt"));
+    }
+
+    #[test]
+    fn tls_key_paths_are_resolved_against_the_rootdir() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        cfg.api.tls = true;
+        cfg.write_config_file(false).unwrap();
+
+        // The default key paths are relative, they are missing until created under the rootdir.
+        let err = cfg.clone().load_with_rootdir(true).unwrap_err();
+        assert!(matches!(err, ConfigError::MissingTLSCertificate(_)), "{:?}", err);
+
+        fs::create_dir_all(dir.path().join("certs")).unwrap();
+        fs::write(dir.path().join(&cfg.api.pub_key_path), "").unwrap();
+        fs::write(dir.path().join(&cfg.api.priv_key_path), "").unwrap();
+        cfg.load_with_rootdir(true).unwrap();
+        assert!(Path::new(&cfg.api.pub_key_path).is_absolute());
+    }
+
+    #[test]
+    fn with_random_ports_zeroes_tcp_and_udp_ports() {
+        let mut net = NetConfig::default_net_config();
+        net.p2p_address = vec!["/ip4/0.0.0.0/tcp/9171".to_string(), "/ip4/0.0.0.0/udp/9171/quic-v1".to_string()];
+        net.peers = "/ip4/10.0.0.1/tcp/9171".to_string();
+        net.with_random_ports().unwrap();
+
+        assert_eq!(net.p2p_address, vec!["/ip4/0.0.0.0/tcp/0", "/ip4/0.0.0.0/udp/0/quic-v1"]);
+        assert!(net.p2p_multiaddr().unwrap().unwrap().iter().any(|protocol| protocol == Protocol::Tcp(0)));
+        assert!(net.peers.is_empty());
+    }
+
+    #[test]
+    fn with_random_ports_rejects_addresses_without_a_port() {
+        let mut net = NetConfig::default_net_config();
+        net.p2p_address = vec!["/ip4/0.0.0.0".to_string()];
+        assert!(matches!(net.with_random_ports(), Err(ConfigError::InvalidP2PAddress(..))));
+
+        net.p2p_address = vec!["not a multiaddr".to_string()];
+        assert!(matches!(net.with_random_ports(), Err(ConfigError::InvalidP2PAddress(..))));
+    }
+
+    #[test]
+    fn named_logger_names_are_lowercase_letters() {
+        let named = |name: &str| NamedLoggingConfig { name: name.to_string(), logging_config: LoggingConfig::default_log_config() };
+        assert!(matches!(named("Net").validate(), Err(ConfigError::InvalidNamedLoggerName(name)) if name == "Net"));
+        assert!(matches!(named("").validate(), Err(ConfigError::InvalidNamedLoggerName(_))));
+        assert!(named("net").validate().is_ok());
+    }
+
+    #[test]
+    fn logger_names_in_the_level_and_logger_settings_are_checked() {
+        let mut log = LoggingConfig::default_log_config();
+        log.logger = "Net,level=debug".to_string();
+        assert!(matches!(log.validate(), Err(ConfigError::InvalidNamedLoggerName(_))));
+
+        log.logger = String::new();
+        log.level = "info,Net=debug".to_string();
+        assert!(matches!(log.validate(), Err(ConfigError::InvalidNamedLoggerName(_))));
+
+        log.level = "info,net=debug".to_string();
+        log.logger = "db,level=warn".to_string();
+        log.validate().unwrap();
+    }
+
+    #[test]
+    fn rust_log_sets_the_global_and_module_levels() {
+        let mut env = env_lock();
+        env.set(RUST_LOG_ENV, "net=debug,info");
+        let mut log = LoggingConfig::default_log_config();
+        log.level = "warn,db=error".to_string();
+        log.resolve().unwrap();
+
+        assert_eq!(log.level, "info");
+        assert_eq!(log.named_overrides["net"].logging_config.level, "debug");
+        assert_eq!(log.named_overrides["db"].logging_config.level, "error");
+    }
+
+    #[test]
+    fn rust_log_skips_modules_that_are_not_logger_names() {
+        assert_eq!(parse_rust_log("Net=debug,defradb_rs=info,crate::net=warn,p2p,off,warn"), (Some(LogLevel::Warn), Vec::new()));
+        assert_eq!(parse_rust_log("net=trace/foo"), (None, vec![("net".to_string(), LogLevel::Trace)]));
+    }
+
+    #[test]
+    fn rust_log_modules_pass_validation_after_loading() {
+        let mut env = env_lock();
+        env.set(RUST_LOG_ENV, "Net=debug,net=debug");
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.load_with_rootdir(false).unwrap();
+
+        cfg.validate_only().unwrap();
+        assert_eq!(cfg.log.named_overrides.keys().collect::<Vec<_>>(), vec!["net"]);
+    }
+
+    #[test]
+    fn badger_path_needs_an_existing_parent() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut datastore = DatastoreConfig::default_data_store_config();
+        datastore.badger.path = dir.path().join("data").display().to_string();
+        datastore.validate().unwrap();
+
+        datastore.badger.path = dir.path().join("missing/data").display().to_string();
+        assert!(matches!(datastore.validate(), Err(ConfigError::InvalidDatastorePath(_))));
+    }
+
+    #[test]
+    fn validate_only_leaves_the_filesystem_untouched() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        cfg.datastore.store = "badger".to_string();
+        cfg.datastore.badger.path = dir.path().join("data").display().to_string();
+
+        cfg.validate_only().unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn loading_probes_the_badger_path_and_cleans_up() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        cfg.datastore.store = "badger".to_string();
+        cfg.datastore.badger.path = "data".to_string();
+        fs::create_dir(dir.path().join("data")).unwrap();
+
+        cfg.load_with_rootdir(false).unwrap();
+        assert_eq!(fs::read_dir(dir.path().join("data")).unwrap().count(), 0);
+
+        cfg.datastore.badger.path = dir.path().join("missing/data").display().to_string();
+        assert!(matches!(cfg.load(), Err(ConfigError::InvalidDatastorePath(_))));
+    }
+
+    #[test]
+    fn badger_path_must_be_under_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        let mut datastore = DatastoreConfig::default_data_store_config();
+
+        datastore.badger.path = file.display().to_string();
+        assert!(matches!(datastore.validate(), Err(ConfigError::InvalidDatastorePath(_))));
+        datastore.badger.path = file.join("data").display().to_string();
+        assert!(matches!(datastore.validate(), Err(ConfigError::InvalidDatastorePath(_))));
+    }
+
+    #[test]
+    fn normalized_config_round_trips_through_to_bytes() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        cfg.log.level = "error,net=debug".to_string();
+        cfg.datastore.badger.path = "data/badger".to_string();
+        cfg.datastore.badger.value_log_file_size = ByteSize::from_str("1.5 KiB").unwrap();
+        cfg.normalize().unwrap();
+
+        let path = dir.path().join("roundtrip.yaml");
+        fs::write(&path, cfg.to_bytes().unwrap()).unwrap();
+        let mut reparsed = Config::default_config().unwrap();
+        reparsed.set_rootdir(&cfg.rootdir).unwrap();
+        reparsed.merge_file(&path, Some(FileFormat::Yaml)).unwrap();
+        reparsed.normalize().unwrap();
+
+        assert_eq!(reparsed.diff(&cfg).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn default_config_renders_a_template_that_parses_back() {
+        let _env = env_lock();
+        let (cfg, dir) = Config::clone_for_test();
+
+        let path = dir.path().join("default.yaml");
+        fs::write(&path, cfg.to_bytes().unwrap()).unwrap();
+        let mut reparsed = Config::default_config().unwrap();
+        reparsed.set_rootdir(&cfg.rootdir).unwrap();
+        reparsed.merge_file(&path, Some(FileFormat::Yaml)).unwrap();
+
+        assert_eq!(reparsed.diff(&cfg).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn default_config_has_the_default_datastore() {
+        let _env = env_lock();
+        let cfg = Config::default_config().unwrap();
+
+        assert_eq!(cfg.datastore.store, "badger");
+        assert_eq!(cfg.datastore.badger.path, "data");
+        assert_eq!(cfg.datastore.badger.value_log_file_size, GiB);
+        assert_eq!(cfg.datastore.memory.size, GiB.as_bytes());
+        assert_eq!(cfg.datastore.max_txn_retries, 5);
+    }
+
+    #[test]
+    fn parse_peers_splits_the_peer_list() {
+        let a = "/ip4/127.0.0.1/tcp/9171";
+        let b = "/ip4/10.0.0.2/tcp/9171";
+        let mut net = NetConfig::default_net_config();
+        let peers = |net: &NetConfig| net.parse_peers().unwrap().iter().map(Multiaddr::to_string).collect::<Vec<_>>();
+
+        assert!(net.parse_peers().unwrap().is_empty());
+        net.peers = a.to_string();
+        assert_eq!(peers(&net), vec![a]);
+        net.peers = format!("{} , {}", a, b);
+        assert_eq!(peers(&net), vec![a, b]);
+        net.peers = format!("{},{},", a, b);
+        assert_eq!(peers(&net), vec![a, b]);
+
+        net.peers = format!("{},not-a-multiaddr", a);
+        assert!(matches!(net.parse_peers(), Err(ConfigError::InvalidBootstrapPeers(..))));
+    }
+
+    #[test]
+    fn p2p_multiaddr_parses_the_listen_address() {
+        let mut net = NetConfig::default_net_config();
+        assert_eq!(net.p2p_multiaddr().unwrap(), Some("/ip4/0.0.0.0/tcp/9171".parse().unwrap()));
+
+        net.p2p_address = vec!["/ip4/0.0.0.0/tcp/notaport".to_string()];
+        assert!(matches!(net.p2p_multiaddr(), Err(ConfigError::InvalidP2PAddress(..))));
+
+        net.p2p_disabled = true;
+        assert_eq!(net.p2p_multiaddr().unwrap(), None);
+    }
+
+    #[test]
+    fn default_tls_paths_point_at_the_cert_and_the_key() {
+        let api = APIConfig::default_api_config();
+        assert_eq!(api.pub_key_path, "certs/server.crt");
+        assert_eq!(api.priv_key_path, "certs/server.key");
+    }
+
+    #[test]
+    fn api_email_must_be_an_address() {
+        let mut api = APIConfig::default_api_config();
+        api.validate().unwrap();
+
+        for invalid in ["", "foo", "a@b@c", "@example.com", "foo@"] {
+            api.email = invalid.to_string();
+            assert!(matches!(api.validate(), Err(ConfigError::InvalidEmail(_))), "{}", invalid);
+        }
+        api.email = "admin@source.network".to_string();
+        api.validate().unwrap();
+    }
+
+    #[test]
+    fn allowed_origins_must_be_origins() {
+        let mut api = APIConfig::default_api_config();
+        api.allowed_origins = vec!["*".to_string(), "https://Example.com/".to_string(), "http://localhost:3000".to_string()];
+        api.validate().unwrap();
+        assert_eq!(api.normalized_origins(), vec!["*", "https://example.com", "http://localhost:3000"]);
+
+        for invalid in ["example.com", "://example.com", "http://", "http://example.com:port", "http://exa mple.com"] {
+            api.allowed_origins = vec![invalid.to_string()];
+            assert!(matches!(api.validate(), Err(ConfigError::InvalidAllowedOrigin(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn api_address_rules() {
+        // The address and the name of the error it fails with, if any.
+        let cases = [
+            ("localhost:9181", None),
+            ("127.0.0.1:9181", None),
+            ("0.0.0.0:0", None),
+            ("example.com", None),
+            ("localhost", Some("MissingPortNumber")),
+            ("127.0.0.1", Some("MissingPortNumber")),
+            ("example.com:9181", Some("NoPortWithDomain")),
+            ("localhost:port", Some("InvalidDatabaseURL")),
+            ("", Some("InvalidDatabaseURL")),
+        ];
+
+        let mut api = APIConfig::default_api_config();
+        for (address, expected) in cases {
+            api.address = address.to_string();
+            let error = api.validate().err().map(|err| format!("{:?}", err));
+            assert_eq!(error.as_deref(), expected, "{}", address);
+        }
+    }
+
+    #[test]
+    fn paths_are_joined_onto_a_rootdir_without_a_trailing_slash() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        assert!(!cfg.rootdir.ends_with('/'));
+        cfg.load_with_rootdir(false).unwrap();
+
+        assert_eq!(PathBuf::from(cfg.config_file_path()), root.join("config.yaml"));
+        assert_eq!(PathBuf::from(&cfg.datastore.badger.path), root.join("data"));
+    }
+
+    #[test]
+    fn config_round_trips_through_json() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.log.level = "debug".to_string();
+
+        let parsed = Config::from_json(&cfg.to_json().unwrap()).unwrap();
+        assert_eq!(parsed.diff(&cfg).unwrap(), Vec::new());
+        assert!(parsed.config.get_str("log.level").is_err());
+        assert!(matches!(Config::from_json("{}"), Err(ConfigError::ConfigToJSONFailed(_))));
+    }
+
+    #[test]
+    fn module_levels_become_logger_config_overrides() {
+        let _env = env_lock();
+        let mut log = LoggingConfig::default_log_config();
+        log.level = "info,net=debug".to_string();
+        log.resolve().unwrap();
+
+        let c = log.to_logger_config().unwrap();
+        assert_eq!(c.level, LevelFilter::Info);
+        assert_eq!(c.overrides_by_logger_name.len(), 1);
+        assert_eq!(c.overrides_by_logger_name["net"].level, LevelFilter::Debug);
+        assert_eq!(c.overrides_by_logger_name["net"].format, LogFormat::Csv);
+        assert_eq!(c.max_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn log_formats_are_csv_json_or_text() {
+        let mut log = LoggingConfig::default_log_config();
+        for format in ["csv", "json", "text"] {
+            log.format = format.to_string();
+            log.logger = format!("net,format={}", format);
+            log.validate().unwrap();
+        }
+
+        log.format = "xml".to_string();
+        assert!(matches!(log.validate(), Err(ConfigError::InvalidLogFormat(_))));
+        log.format = "csv".to_string();
+        log.logger = "net,format=xml".to_string();
+        assert!(matches!(log.validate(), Err(ConfigError::InvalidLogFormat(_))));
+    }
+
+    #[test]
+    fn log_levels_are_the_lowercase_level_names() {
+        let mut log = LoggingConfig::default_log_config();
+        log.validate().unwrap();
+
+        for invalid in ["INFO", "LOG_LEVEL_INFO", "warning", ""] {
+            log.level = invalid.to_string();
+            assert!(matches!(log.validate(), Err(ConfigError::InvalidLogLevel(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn every_log_level_is_accepted_globally_and_per_logger() {
+        let _env = env_lock();
+        for level in ["trace", "debug", "info", "warn", "error", "fatal"] {
+            let mut log = LoggingConfig::default_log_config();
+            log.level = format!("{},db={}", level, level);
+            log.logger = format!("net,level={}", level);
+            log.validate().unwrap();
+            log.resolve().unwrap();
+
+            assert_eq!(log.level, level);
+            assert_eq!(log.named_overrides["db"].logging_config.level, level);
+            assert_eq!(log.named_overrides["net"].logging_config.level, level);
+            log.to_logger_config().unwrap();
+        }
+    }
+
+    #[test]
+    fn named_loggers_do_not_nest_overrides() {
+        let _env = env_lock();
+        let mut log = LoggingConfig::default_log_config();
+        log.level = "info,net=debug,db=warn".to_string();
+        log.logger = "api,level=error".to_string();
+        log.resolve().unwrap();
+
+        assert_eq!(log.named_overrides.len(), 3);
+        for named in log.named_overrides.values() {
+            assert!(named.logging_config.named_overrides.is_empty(), "{}", named.name);
+            assert!(named.logging_config.logger.is_empty(), "{}", named.name);
+        }
+    }
+
+    #[test]
+    fn a_logger_may_only_be_named_once() {
+        let mut log = LoggingConfig::default_log_config();
+        log.logger = "net,level=debug;db,level=warn".to_string();
+        log.validate().unwrap();
+
+        log.logger = "net,level=debug;net,format=json".to_string();
+        assert!(matches!(log.validate(), Err(ConfigError::DuplicateLoggerName(name)) if name == "net"));
+        log.logger = String::new();
+        log.level = "info,net=debug,net=warn".to_string();
+        assert!(matches!(log.validate(), Err(ConfigError::DuplicateLoggerName(name)) if name == "net"));
+    }
+
+    #[test]
+    fn merge_file_layers_yaml_and_json_fragments() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        let yaml = dir.path().join("log.yaml");
+        let json = dir.path().join("api.json");
+        fs::write(&yaml, "log:\n  level: debug\n").unwrap();
+        fs::write(&json, r#"{"api": {"address": "127.0.0.1:9999"}}"#).unwrap();
+
+        cfg.merge_file(&yaml, Some(FileFormat::Yaml)).unwrap();
+        cfg.merge_file(&json, Some(FileFormat::Json)).unwrap();
+        assert_eq!(cfg.log.level, "debug");
+        assert_eq!(cfg.api.address, "127.0.0.1:9999");
+        assert_eq!(cfg.log.format, "csv");
+        assert!(!cfg.api.tls);
+
+        let err = cfg.merge_file(&dir.path().join("missing.yaml"), Some(FileFormat::Yaml)).unwrap_err();
+        assert!(matches!(err, ConfigError::ReadingConfigFile(_)), "{:?}", err);
+        assert_eq!(cfg.log.level, "debug");
+    }
+
+    #[test]
+    fn a_missing_config_file_keeps_the_defaults() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        assert!(!cfg.config_file_exists());
+
+        cfg.load_with_rootdir(true).unwrap();
+        assert_eq!(cfg.api.address, "localhost:9181");
+    }
+
+    #[test]
+    fn a_malformed_config_file_fails_to_load() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        fs::write(cfg.config_file_path(), "api:\n  tls: not-a-bool\n").unwrap();
+
+        let err = cfg.load_with_rootdir(true).unwrap_err();
+        assert!(matches!(err, ConfigError::LoadingConfig(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn a_relative_rootdir_becomes_absolute() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.rootdir = "relative-rootdir".to_string();
+        cfg.load_with_rootdir(false).unwrap();
+
+        let rootdir = Path::new(&cfg.rootdir);
+        assert!(rootdir.is_absolute());
+        assert_eq!(rootdir, std::env::current_dir().unwrap().join("relative-rootdir"));
+        assert!(!rootdir.exists());
+
+        cfg.rootdir = String::new();
+        cfg.load_with_rootdir(false).unwrap();
+        assert!(Path::new(&cfg.rootdir).ends_with(".defradb"));
+    }
+
+    #[test]
+    fn default_with_rootdir_uses_the_given_dir() {
+        let _env = env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let cfg = Config::default_with_rootdir(dir.path().to_str().unwrap()).unwrap();
+
+        assert_eq!(Path::new(&cfg.config_file_path()).parent(), Some(root.as_path()));
+        assert!(Path::new(&cfg.datastore.badger.path).starts_with(&root));
+    }
+
+    #[test]
+    fn validate_only_reports_the_invalid_section() {
+        let _env = env_lock();
+        let (cfg, _dir) = Config::clone_for_test();
+        cfg.validate_only().unwrap();
+
+        let mut bad_net = cfg.clone();
+        bad_net.net.p2p_address = vec!["not-a-multiaddr".to_string()];
+        assert!(matches!(bad_net.validate_only(), Err(ConfigError::InvalidP2PAddress(..))));
+
+        let mut bad_log = cfg.clone();
+        bad_log.log.level = "loud".to_string();
+        assert!(matches!(bad_log.validate_only(), Err(ConfigError::InvalidLogLevel(_))));
+    }
+
+    #[test]
+    fn allowed_origins_accept_a_list_or_a_comma_separated_string() {
+        let api = |origins: serde_json::Value| {
+            let mut value = serde_json::to_value(APIConfig::default_api_config()).unwrap();
+            value["allowed_origins"] = origins;
+            serde_json::from_value::<APIConfig>(value).unwrap().allowed_origins
+        };
+
+        let expected = vec!["http://localhost:3000".to_string(), "https://example.com".to_string()];
+        assert_eq!(api(serde_json::json!(["http://localhost:3000", "https://example.com"])), expected);
+        assert_eq!(api(serde_json::json!("http://localhost:3000, https://example.com,")), expected);
+        assert!(api(serde_json::json!("")).is_empty());
+    }
+
+    #[test]
+    fn the_memory_store_needs_a_minimum_size() {
+        let mut datastore = DatastoreConfig::default_data_store_config();
+        datastore.memory.size = 0;
+        datastore.validate().unwrap();
+
+        datastore.store = "memory".to_string();
+        assert!(matches!(datastore.validate(), Err(ConfigError::InvalidMemorySize(0))));
+        datastore.memory.size = GiB.as_bytes();
+        datastore.validate().unwrap();
+    }
+
+    #[test]
+    fn the_memory_datastore_opens_a_working_store() {
+        let mut datastore = DatastoreConfig::default_data_store_config();
+        datastore.store = "memory".to_string();
+
+        let store = datastore.open().unwrap();
+        store.put(b"k", b"v").unwrap();
+        assert_eq!(store.get(b"k").unwrap(), Some(b"v".to_vec()));
+
+        datastore.store = "unknown".to_string();
+        assert!(datastore.open().is_err());
+    }
+
+    #[test]
+    fn badger_paths_are_expanded_and_resolved() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let home = dirs::home_dir().unwrap();
+        let badger_path = |cfg: &mut Config, path: &str| {
+            cfg.datastore.badger.path = path.to_string();
+            cfg.params_preprocessing().unwrap();
+            PathBuf::from(&cfg.datastore.badger.path)
+        };
+
+        assert_eq!(badger_path(&mut cfg, "~/data"), home.join("data"));
+        assert_eq!(badger_path(&mut cfg, "./data"), root.join("./data"));
+        assert_eq!(badger_path(&mut cfg, "/var/lib/defra"), PathBuf::from("/var/lib/defra"));
+    }
+
+    #[test]
+    fn home_relative_key_paths_are_rewritten() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.api.priv_key_path = "~/certs/server.key".to_string();
+        cfg.params_preprocessing().unwrap();
+
+        assert_eq!(PathBuf::from(&cfg.api.priv_key_path), dirs::home_dir().unwrap().join("certs/server.key"));
+
+        cfg.api.pub_key_path = "~".to_string();
+        assert!(matches!(cfg.params_preprocessing(), Err(ConfigError::PathCannotBeHomeDir)));
+    }
+
+    #[test]
+    fn a_disabled_net_skips_the_address_checks() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.net.p2p_address = vec!["garbage".to_string()];
+        assert!(cfg.p2p_enabled());
+        assert!(matches!(cfg.validate_only(), Err(ConfigError::InvalidP2PAddress(..))));
+
+        cfg.net.p2p_disabled = true;
+        cfg.net.pub_sub_enabled = false;
+        assert!(!cfg.p2p_enabled());
+        cfg.validate_only().unwrap();
+    }
+
+    #[test]
+    fn pubsub_and_relay_require_p2p() {
+        let net = |p2p_disabled, pub_sub_enabled, relay_enabled| NetConfig {
+            p2p_disabled,
+            pub_sub_enabled,
+            relay_enabled,
+            ..NetConfig::default_net_config()
+        };
+
+        assert!(matches!(net(true, true, false).validate(), Err(ConfigError::ContradictoryNetConfig(_))));
+        assert!(matches!(net(true, false, true).validate(), Err(ConfigError::ContradictoryNetConfig(_))));
+        assert!(matches!(net(true, true, true).validate(), Err(ConfigError::ContradictoryNetConfig(_))));
+        net(true, false, false).validate().unwrap();
+        net(false, true, true).validate().unwrap();
+    }
+
+    #[test]
+    fn logger_overrides_parse_into_typed_values() {
+        let overrides = parse_logger_overrides("net,level=debug,format=json,NoColor=true;db,output=stdout,caller=true").unwrap();
+        assert_eq!(
+            overrides,
+            vec![
+                LoggerOverride {
+                    name: "net".to_string(),
+                    level: Some(LogLevel::Debug),
+                    format: Some(LogFormat::Json),
+                    no_color: Some(true),
+                    ..Default::default()
+                },
+                LoggerOverride {
+                    name: "db".to_string(),
+                    output: Some(LogOutput::Stdout),
+                    caller: Some(true),
+                    ..Default::default()
+                },
+            ]
+        );
+
+        assert!(matches!(parse_logger_overrides("net"), Err(ConfigError::InvalidLoggerConfig(_))));
+        assert!(matches!(parse_logger_overrides("net,level"), Err(ConfigError::NotProvidedAsKV(_))));
+        assert!(matches!(parse_logger_overrides("net,colour=red"), Err(ConfigError::UnknownLoggerParameter(_))));
+        assert!(matches!(parse_logger_overrides("net,caller=yes"), Err(ConfigError::CouldNotParseType(_))));
+        assert!(matches!(parse_logger_overrides("net,level=loud"), Err(ConfigError::InvalidLogLevel(_))));
+        assert!(matches!(parse_logger_overrides(",level=info"), Err(ConfigError::InvalidNamedLoggerName(_))));
+    }
+
+    #[test]
+    fn load_peers_file_skips_comments_and_blank_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peers");
+        fs::write(&path, "# bootstrap peers\n\n/ip4/10.0.0.1/tcp/9171\n  /ip4/10.0.0.2/tcp/9171  \n").unwrap();
+        let mut net = NetConfig::default_net_config();
+        net.peers = "/ip4/10.0.0.0/tcp/9171".to_string();
+
+        net.load_peers_file(&path).unwrap();
+        assert_eq!(net.peers, "/ip4/10.0.0.0/tcp/9171,/ip4/10.0.0.1/tcp/9171,/ip4/10.0.0.2/tcp/9171");
+
+        fs::write(&path, "/ip4/10.0.0.3/tcp/9171\nnot-a-peer\n").unwrap();
+        assert!(matches!(net.load_peers_file(&path), Err(ConfigError::InvalidBootstrapPeers(_, line)) if line == "not-a-peer"));
+        assert_eq!(net.parse_peers().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn dump_effective_expands_home_paths() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.datastore.badger.path = "~/defra-data".to_string();
+
+        let dump = cfg.dump_effective().unwrap();
+        let expanded = dirs::home_dir().unwrap().join("defra-data");
+        assert!(dump.contains(&expanded.display().to_string()), "{}", dump);
+        assert!(!dump.contains("~/defra-data"));
+        assert_eq!(cfg.datastore.badger.path, "~/defra-data");
+    }
+
+    #[test]
+    fn incomplete_module_levels_fail_validate_and_load_alike() {
+        let _env = env_lock();
+        for level in ["info,net=", "info,=debug"] {
+            let mut log = LoggingConfig::default_log_config();
+            log.level = level.to_string();
+            let validated = log.validate().unwrap_err();
+            let loaded = log.load().unwrap_err();
+
+            assert!(matches!(validated, ConfigError::NotProvidedAsKV(_)), "{}: {:?}", level, validated);
+            assert_eq!(validated.to_string(), loaded.to_string());
+        }
+    }
+
+    #[test]
+    fn api_base_url_includes_the_scheme() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.api.address = "127.0.0.1:9181".to_string();
+        assert_eq!(cfg.api_base_url().unwrap(), "http://127.0.0.1:9181/");
+
+        cfg.api.address = "example.com".to_string();
+        cfg.api.tls = true;
+        assert_eq!(cfg.api_base_url().unwrap(), "https://example.com/");
+
+        cfg.api.address = "exa mple.com".to_string();
+        assert!(matches!(cfg.api_base_url(), Err(ConfigError::InvalidDatabaseURL)));
+    }
+
+    #[test]
+    fn port_zero_is_allowed() {
+        let mut api = APIConfig::default_api_config();
+        api.address = "127.0.0.1:0".to_string();
+        api.validate().unwrap();
+
+        let mut net = NetConfig::default_net_config();
+        net.p2p_address = vec!["/ip4/0.0.0.0/tcp/0".to_string()];
+        net.validate().unwrap();
+    }
+
+    #[test]
+    fn ipv6_addresses() {
+        let mut api = APIConfig::default_api_config();
+        let mut check = |address: &str| {
+            api.address = address.to_string();
+            (api.validate().map_err(|err| format!("{:?}", err)), api.is_loopback())
+        };
+
+        assert_eq!(check("[::1]:9181"), (Ok(()), true));
+        assert_eq!(check("[::1]:0"), (Ok(()), true));
+        assert_eq!(check("[2001:db8::1]:9181"), (Ok(()), false));
+        assert_eq!(check("[::1]").0, Err("MissingPortNumber".to_string()));
+        assert_eq!(check("::1").0, Err("MissingPortNumber".to_string()));
+        assert_eq!(check("[::1:9181").0, Err("InvalidDatabaseURL".to_string()));
+        assert_eq!(check("[not-ipv6]:9181").0, Err("InvalidDatabaseURL".to_string()));
+    }
+
+    #[test]
+    fn yaml_syntax_errors_report_their_line() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        let path = dir.path().join("broken.yaml");
+        fs::write(&path, "log:\n  level: info\napi:\n  address: \"localhost:9181\n").unwrap();
+
+        match cfg.merge_file(&path, Some(FileFormat::Yaml)) {
+            Err(ConfigError::ParseAt { line, col, msg }) => {
+                assert_eq!(line, 4, "{}", msg);
+                assert!(col > 0);
+            }
+            other => panic!("expected a located parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn log_outputs_are_streams_or_files() {
+        let _env = env_lock();
+        assert_eq!("stderr".parse::<LogOutput>().unwrap(), LogOutput::Stderr);
+        assert_eq!("stdout".parse::<LogOutput>().unwrap(), LogOutput::Stdout);
+        assert!(matches!("".parse::<LogOutput>(), Err(ConfigError::InvalidLoggerConfig(_))));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs/defra.log");
+        let mut log = LoggingConfig::default_log_config();
+        log.output = path.display().to_string();
+        log.validate().unwrap();
+        log.load().unwrap();
+        assert!(path.is_file());
+
+        log.output = dir.path().display().to_string();
+        assert!(matches!(log.validate(), Err(ConfigError::InvalidLoggerConfig(_))));
+    }
+
+    #[test]
+    fn badger_value_log_size_in_bytes() {
+        let mut datastore = DatastoreConfig::default_data_store_config();
+        datastore.badger.value_log_file_size = ByteSize::from_str("1GiB").unwrap();
+        assert_eq!(datastore.badger_value_log_size_bytes(), 1073741824);
+    }
+
+    #[test]
+    fn diff_lists_the_changed_settings() {
+        let _env = env_lock();
+        let (cfg, _dir) = Config::clone_for_test();
+        let mut other = cfg.clone();
+        assert!(cfg.diff(&other).unwrap().is_empty());
+
+        other.log.level = "debug".to_string();
+        assert_eq!(
+            cfg.diff(&other).unwrap(),
+            vec![ConfigChange {
+                path: "log.level".to_string(),
+                old: "info".to_string(),
+                new: "debug".to_string(),
+                requires_restart: false,
+            }]
+        );
+
+        other.net.relay_enabled = true;
+        let changes = cfg.diff(&other).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().any(|change| change.path == "net.relay_enabled" && change.requires_restart));
+    }
+
+    #[test]
+    fn sanitize_redacts_the_private_key_path() {
+        let _env = env_lock();
+        let (cfg, _dir) = Config::clone_for_test();
+        let sanitized = cfg.sanitize();
+
+        assert_eq!(sanitized.api.priv_key_path, REDACTED);
+        assert_eq!(sanitized.api.pub_key_path, cfg.api.pub_key_path);
+        assert_eq!(cfg.api.priv_key_path, "certs/server.key");
+
+        let mut unset = cfg.clone();
+        unset.api.priv_key_path = String::new();
+        assert_eq!(unset.sanitize().api.priv_key_path, "");
+    }
+
+    #[test]
+    fn p2p_address_accepts_one_or_more_addresses() {
+        let net = |address: serde_json::Value| {
+            let mut value = serde_json::to_value(NetConfig::default_net_config()).unwrap();
+            value["p2p_address"] = address;
+            serde_json::from_value::<NetConfig>(value).unwrap()
+        };
+
+        let single = net(serde_json::json!("/ip4/0.0.0.0/tcp/9171"));
+        assert_eq!(single.p2p_multiaddrs().unwrap().len(), 1);
+
+        let both = net(serde_json::json!(["/ip4/0.0.0.0/tcp/9171", "/ip4/0.0.0.0/udp/9171/quic-v1"]));
+        let addrs = both.p2p_multiaddrs().unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(both.p2p_multiaddr().unwrap(), Some(addrs[0].clone()));
+        both.validate().unwrap();
+    }
+
+    #[test]
+    fn get_section_deserializes_a_single_section() {
+        let _env = env_lock();
+        let (cfg, _dir) = Config::clone_for_test();
+
+        let net: NetConfig = cfg.get_section("Net").unwrap();
+        assert_eq!(net.p2p_address, vec!["/ip4/0.0.0.0/tcp/9171"]);
+        assert!(net.pub_sub_enabled);
+        assert!(cfg.get_section::<NetConfig>("missing").is_err());
+    }
+
+    #[test]
+    fn bootstrap_peers_exclude_our_own_address() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.net.p2p_address = vec!["/ip4/127.0.0.1/tcp/9171".to_string()];
+        cfg.net.peers = "/ip4/127.0.0.1/tcp/9171,/ip4/10.0.0.2/tcp/9171".to_string();
+
+        let peers = cfg.bootstrap_peers_with_self_filter().unwrap();
+        assert_eq!(peers, vec!["/ip4/10.0.0.2/tcp/9171".parse::<Multiaddr>().unwrap()]);
+    }
+
+    #[test]
+    fn bind_addr_resolves_the_api_address() {
+        let mut api = APIConfig::default_api_config();
+        api.address = "127.0.0.1:9181".to_string();
+        assert_eq!(api.bind_addr().unwrap(), "127.0.0.1:9181".parse::<SocketAddr>().unwrap());
+
+        api.address = "localhost:0".to_string();
+        let addr = api.bind_addr().unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_eq!(addr.port(), 0);
+
+        api.address = "localhost".to_string();
+        assert!(matches!(api.bind_addr(), Err(ConfigError::InvalidDatabaseURL)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_rootdirs_resolve_to_their_target() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        let target = dir.path().join("target");
+        let link = dir.path().join("link");
+        fs::create_dir(&target).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        cfg.set_rootdir(&link.to_string_lossy()).unwrap();
+        assert_eq!(cfg.rootdir, fs::canonicalize(&target).unwrap().to_string_lossy());
+
+        let dangling = dir.path().join("dangling");
+        std::os::unix::fs::symlink(dir.path().join("missing"), &dangling).unwrap();
+        let err = cfg.set_rootdir(&dangling.to_string_lossy()).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidRootDir(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn max_txn_retries_must_be_within_bounds() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        for retries in [0, MAX_TXN_RETRIES] {
+            cfg.datastore.max_txn_retries = retries;
+            cfg.datastore.validate().unwrap();
+        }
+        for retries in [-1, MAX_TXN_RETRIES + 1, 1000] {
+            cfg.datastore.max_txn_retries = retries;
+            let err = cfg.datastore.validate().unwrap_err();
+            assert!(matches!(err, ConfigError::InvalidMaxTxnRetries(r) if r == retries), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn log_level_filter_maps_the_global_level() {
+        let mut log = LoggingConfig::default_log_config();
+        for (level, filter) in [
+            ("trace", LevelFilter::Trace),
+            ("debug", LevelFilter::Debug),
+            ("info", LevelFilter::Info),
+            ("warn", LevelFilter::Warn),
+            ("error", LevelFilter::Error),
+            ("fatal", LevelFilter::Error),
+            ("debug,net=trace", LevelFilter::Debug),
+            ("verbose", LevelFilter::Info),
+        ] {
+            log.level = level.to_string();
+            assert_eq!(log.log_level_filter(), filter, "{}", level);
+        }
+    }
+
+    #[test]
+    fn no_color_env_and_terminals_decide_the_colors() {
+        let set = |value: &str| Some(OsString::from(value));
+        assert!(!effective_no_color(false, None, true));
+        assert!(effective_no_color(true, None, true));
+        assert!(effective_no_color(false, set("1"), true));
+        assert!(!effective_no_color(false, set(""), true));
+        assert!(effective_no_color(false, None, false));
+        assert!(effective_no_color(false, set(""), false));
+    }
+
+    #[test]
+    fn env_peers_replace_or_extend_the_file_peers() {
+        let mut env = env_lock();
+        env.set("DEFRA_NET_PEERS", "/ip4/10.0.0.2/tcp/9171,/ip4/10.0.0.3/tcp/9171");
+        let (mut cfg, dir) = Config::clone_for_test();
+        let path = dir.path().join("peers.yaml");
+        fs::write(&path, "net:\n  peers: /ip4/10.0.0.1/tcp/9171,/ip4/10.0.0.2/tcp/9171\n").unwrap();
+        cfg.merge_file(&path, Some(FileFormat::Yaml)).unwrap();
+        let addrs = |addrs: &[&str]| addrs.iter().map(|a| format!("/ip4/{}/tcp/9171", a).parse::<Multiaddr>().unwrap()).collect::<Vec<_>>();
+
+        assert_eq!(cfg.effective_peers().unwrap(), addrs(&["10.0.0.2", "10.0.0.3"]));
+
+        cfg.net.union_env_peers = true;
+        assert_eq!(cfg.effective_peers().unwrap(), addrs(&["10.0.0.1", "10.0.0.2", "10.0.0.3"]));
+    }
+
+    #[test]
+    fn validate_only_checks_the_module_levels() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.log.level = "info,net=debug".to_string();
+        cfg.validate_only().unwrap();
+
+        cfg.log.level = "info,net=bogus".to_string();
+        assert!(matches!(cfg.validate_only(), Err(ConfigError::InvalidLogLevel(level)) if level == "bogus"));
+    }
+
+    #[test]
+    fn misspelled_streams_are_not_log_files() {
+        assert!(matches!("stdrr".parse::<LogOutput>(), Err(ConfigError::InvalidLoggerConfig(_))));
+        assert_eq!("./defra.log".parse::<LogOutput>().unwrap(), LogOutput::File(PathBuf::from("./defra.log")));
+
+        let mut log = LoggingConfig::default_log_config();
+        log.output = "stdrr".to_string();
+        assert!(matches!(log.validate(), Err(ConfigError::InvalidLoggerConfig(_))));
+        log.output = "stderr".to_string();
+        log.logger = "net,output=stdrr".to_string();
+        assert!(matches!(log.validate(), Err(ConfigError::InvalidLoggerConfig(_))));
+    }
+
+    #[test]
+    fn relative_log_outputs_are_resolved_against_the_rootdir() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        cfg.log.output = "logs/defra.log".to_string();
+        cfg.log.logger = "net,output=logs/net.log,level=debug;db,output=stdout".to_string();
+
+        cfg.load_with_rootdir(false).unwrap();
+        assert_eq!(Path::new(&cfg.log.output), root.join("logs/defra.log"));
+        assert_eq!(cfg.log.named_overrides["net"].logging_config.output, root.join("logs/net.log").to_string_lossy());
+        assert_eq!(cfg.log.named_overrides["db"].logging_config.output, "stdout");
+        assert!(root.join("logs/defra.log").is_file());
+        assert!(root.join("logs/net.log").is_file());
+    }
+}
@@ -1,46 +1,141 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::io;
-use handlebars::Handlebars;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use config::FileFormat;
+use log::{error, info, warn};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+#[cfg(unix)]
 use once_cell::sync::Lazy;
+#[cfg(unix)]
 use std::fs::Permissions;
+#[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
 use super::Config;
 use super::ConfigError;
 
 const DEFAULT_CONFIG_FILE_NAME: &str = "config.yaml";
+#[cfg(unix)]
 static DEFAULT_DIR_PERM: Lazy<Permissions> = Lazy::new(|| Permissions::from_mode(0o700));
+#[cfg(unix)]
 static DEFAULT_CONFIG_FILE_PERM: Lazy<Permissions> = Lazy::new(|| Permissions::from_mode(0o644));
 
+// How long `Config::watch` waits for a write to settle, saving a file usually raises several events.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(50);
+
 // Embed the default config template
 pub const DEFAULT_CONFIG_TEMPLATE: &str = include_str!("configfile_yaml.gotmpl");
 
 impl Config {
     pub fn config_file_path(&self) -> String {
-        self.rootdir.clone() + DEFAULT_CONFIG_FILE_NAME
+        Path::new(&self.rootdir).join(DEFAULT_CONFIG_FILE_NAME).to_string_lossy().into_owned()
     }
 
-    pub fn write_config_file(&self) -> Result<(), ConfigError> {
+    /// Writes the config file, refusing to overwrite an existing one unless `force` is set.
+    pub fn write_config_file(&self, force: bool) -> Result<(), ConfigError> {
         let path = self.config_file_path();
-        let buffer = self.to_bytes()?;  // to_bytes now returns a Result<String, String>
-        fs::write(&path, buffer).map_err(|e| ConfigError::Custom(format!("Failed to write file: {}", e)))?;
-        println!("Created config file at {:?}", path);  // Replace with proper logging
+        if !force && self.config_file_exists() {
+            return Err(ConfigError::FailedToWriteFile(format!("{} already exists", path)));
+        }
+        let buffer = self.to_bytes()?;
+        fs::write(&path, buffer)?;
+        #[cfg(unix)]
+        set_permissions(&path, &DEFAULT_CONFIG_FILE_PERM)?;
+        info!("Created config file at {}", path);
         Ok(())
     }
 
     pub fn delete_config_file(&self) -> Result<(), ConfigError> {
         let path = self.config_file_path();
-        fs::remove_file(&path).map_err(|e| ConfigError::Custom(format!("Failed to remove config file: {}", e)))?;
-        println!("Deleted config file at {:?}", path);  // Replace with proper logging
+        fs::remove_file(&path)?;
+        info!("Deleted config file at {}", path);
         Ok(())
     }
 
     pub fn create_root_dir_and_config_file(&self) -> Result<(), ConfigError> {
-        fs::create_dir_all(&self.rootdir).map_err(|e| ConfigError::Custom(format!("Failed to create root directory: {}", e)))?;
-        // TODO: replace with proper logging
-        println!("Created root directory at {:?}", self.rootdir);  // Replace with proper logging
-        self.write_config_file()
+        self.ensure_rootdir()?;
+        self.write_config_file(false)
+    }
+
+    /// Creates the rootdir if it doesn't exist yet. Returns whether it was created.
+    pub fn ensure_rootdir(&self) -> Result<bool, ConfigError> {
+        if folder_exists(Path::new(&self.rootdir)) {
+            return Ok(false);
+        }
+        // Creating the directories would fail on the link itself, with an error that doesn't mention it.
+        if is_dangling_symlink(Path::new(&self.rootdir)) {
+            return Err(ConfigError::InvalidRootDir(format!("{} is a symlink to a missing target", self.rootdir)));
+        }
+
+        fs::create_dir_all(&self.rootdir)?;
+        #[cfg(unix)]
+        set_permissions(&self.rootdir, &DEFAULT_DIR_PERM)?;
+        info!("Created root directory at {}", self.rootdir);
+        Ok(true)
+    }
+
+    /// Watches the config file and calls `on_reload` with the reloaded config whenever it changes.
+    /// Only the log section is hot-reloadable, changes to the net or datastore sections are logged as
+    /// requiring a restart and the running values are kept. A file that fails to load is logged and skipped.
+    /// Watching stops when the returned handle is dropped.
+    pub fn watch<F>(&self, on_reload: F) -> Result<WatchHandle, ConfigError>
+    where
+        F: Fn(&Config) + Send + 'static,
+    {
+        let path = PathBuf::from(self.config_file_path());
+        let mut current = self.clone();
+        let mut contents = fs::read(&path).ok();
+
+        // The rootdir is watched rather than the file, editors often save by replacing the file.
+        let (events, received) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(events).map_err(ConfigError::Watch)?;
+        watcher.watch(Path::new(&self.rootdir), RecursiveMode::NonRecursive).map_err(ConfigError::Watch)?;
+
+        // The thread stops once the watcher is dropped, which closes the channel.
+        let thread = thread::spawn(move || {
+            while let Ok(event) = received.recv() {
+                let touches_file = |event: notify::Result<notify::Event>| event.is_ok_and(|event| event.paths.contains(&path));
+                let mut changed = touches_file(event);
+                while let Ok(event) = received.recv_timeout(WATCH_DEBOUNCE) {
+                    changed |= touches_file(event);
+                }
+
+                let latest = fs::read(&path).ok();
+                if !changed || latest.is_none() || latest == contents {
+                    continue;
+                }
+                contents = latest;
+
+                match current.reload(&path) {
+                    Ok(reloaded) => {
+                        current = reloaded;
+                        on_reload(&current);
+                    }
+                    Err(e) => error!("Could not reload config file {}: {}", path.display(), e),
+                }
+            }
+        });
+
+        Ok(WatchHandle { watcher: Some(watcher), thread: Some(thread) })
+    }
+
+    // Loads the file afresh, keeping the sections of this config that can't change without a restart.
+    // The config is rebuilt from the defaults, merging into this one would add another file source on every reload.
+    fn reload(&self, path: &Path) -> Result<Config, ConfigError> {
+        let mut cfg = Config::default_config()?;
+        cfg.set_rootdir(&self.rootdir)?;
+        cfg.merge_file(path, Some(FileFormat::Yaml))?;
+        // Loading resolves the paths, so they compare equal to the ones of the running config.
+        cfg.load_with_rootdir(false)?;
+
+        for change in self.diff(&cfg)?.iter().filter(|change| change.requires_restart) {
+            warn!("Changing {} from {:?} to {:?} in {} requires a restart", change.path, change.old, change.new, path.display());
+        }
+        cfg.net = self.net.clone();
+        cfg.datastore = self.datastore.clone();
+        Ok(cfg)
     }
 
     pub fn config_file_exists(&self) -> bool {
@@ -52,6 +147,21 @@ impl Config {
     }
 }
 
+/// Stops watching the config file when dropped.
+pub struct WatchHandle {
+    watcher: Option<RecommendedWatcher>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.watcher.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
 pub fn default_root_dir() -> PathBuf {
     dirs::home_dir().expect("Failed to get home directory").join(".defradb")
 }
@@ -61,4 +171,154 @@ pub fn folder_exists(folder_path: &Path) -> bool {
         Ok(metadata) => metadata.is_dir(),
         Err(_) => false,
     }
-}
\ No newline at end of file
+}
+
+/// Returns whether `path` is a symlink whose target doesn't exist.
+pub fn is_dangling_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink()) && !path.exists()
+}
+
+// Permissions are only applied on unix, other platforms keep the defaults of the parent directory.
+#[cfg(unix)]
+fn set_permissions(path: &str, perm: &Permissions) -> Result<(), ConfigError> {
+    Ok(fs::set_permissions(path, perm.clone())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Mutex};
+    use std::time::Duration;
+    use log::Level;
+
+    use super::*;
+    use crate::config::test_utils::env_lock;
+
+    #[test]
+    fn watch_reloads_the_log_level() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.write_config_file(false).unwrap();
+        cfg.load_with_rootdir(true).unwrap();
+
+        let (reloaded, received) = mpsc::channel();
+        let handle = cfg.watch(move |cfg| reloaded.send(cfg.clone()).unwrap()).unwrap();
+
+        let contents = fs::read_to_string(cfg.config_file_path()).unwrap();
+        let contents = contents
+            .replace("level: \"info\"", "level: \"debug\"")
+            .replace("relay_enabled: false", "relay_enabled: true");
+        fs::write(cfg.config_file_path(), contents).unwrap();
+
+        let reloaded = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(reloaded.log.level, "debug");
+        // Net changes need a restart, the running value is kept.
+        assert!(!reloaded.net.relay_enabled);
+        drop(handle);
+    }
+
+    #[test]
+    fn watch_skips_a_malformed_file() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.write_config_file(false).unwrap();
+        cfg.load_with_rootdir(true).unwrap();
+
+        let (reloaded, received) = mpsc::channel();
+        let _handle = cfg.watch(move |cfg| reloaded.send(cfg.log.level.clone()).unwrap()).unwrap();
+
+        fs::write(cfg.config_file_path(), "log: [\n").unwrap();
+        assert!(received.recv_timeout(Duration::from_millis(500)).is_err());
+
+        fs::write(cfg.config_file_path(), "log:\n  level: warn\n").unwrap();
+        assert_eq!(received.recv_timeout(Duration::from_secs(5)).unwrap(), "warn");
+    }
+
+    #[test]
+    fn reload_does_not_accumulate_sources() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.write_config_file(false).unwrap();
+        cfg.load_with_rootdir(true).unwrap();
+
+        let path = PathBuf::from(cfg.config_file_path());
+        let mut current = cfg.clone();
+        for _ in 0..3 {
+            current = current.reload(&path).unwrap();
+        }
+        // The sources aren't exposed, the debug output of the inner config lists them.
+        let sources = |cfg: &Config| format!("{:?}", cfg.config).matches("FileSourceFile").count();
+        assert_eq!(sources(&current), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_config_file_is_written_with_0644() {
+        let _env = env_lock();
+        let (cfg, _dir) = Config::clone_for_test();
+        cfg.write_config_file(false).unwrap();
+
+        let mode = fs::metadata(cfg.config_file_path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+    }
+
+    #[test]
+    fn write_config_file_does_not_clobber_unless_forced() {
+        let _env = env_lock();
+        let (mut cfg, _dir) = Config::clone_for_test();
+        cfg.write_config_file(false).unwrap();
+        fs::write(cfg.config_file_path(), "# edited\n").unwrap();
+
+        cfg.log.level = "debug".to_string();
+        assert!(matches!(cfg.write_config_file(false), Err(ConfigError::FailedToWriteFile(_))));
+        assert_eq!(fs::read_to_string(cfg.config_file_path()).unwrap(), "# edited\n");
+
+        cfg.write_config_file(true).unwrap();
+        assert!(fs::read_to_string(cfg.config_file_path()).unwrap().contains("debug"));
+    }
+
+    // Keeps the messages of every record logged by any test, the logger can only be installed once per process.
+    struct CapturingLogger(Mutex<Vec<(Level, String)>>);
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+
+    #[test]
+    fn writing_the_config_file_logs_an_info_record() {
+        let _env = env_lock();
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Trace);
+        let (cfg, _dir) = Config::clone_for_test();
+        cfg.write_config_file(false).unwrap();
+
+        let expected = (Level::Info, format!("Created config file at {}", cfg.config_file_path()));
+        assert!(LOGGER.0.lock().unwrap().contains(&expected));
+    }
+
+    #[test]
+    fn ensure_rootdir_creates_a_missing_rootdir_once() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        cfg.rootdir = dir.path().join("fresh").to_string_lossy().into_owned();
+        assert!(!folder_exists(Path::new(&cfg.rootdir)));
+        assert!(!cfg.config_file_exists());
+
+        assert!(cfg.ensure_rootdir().unwrap());
+        assert!(folder_exists(Path::new(&cfg.rootdir)));
+        assert!(!cfg.ensure_rootdir().unwrap());
+
+        cfg.write_config_file(false).unwrap();
+        assert!(cfg.config_file_exists());
+        assert!(!folder_exists(Path::new(&cfg.config_file_path())));
+    }
+}
@@ -1,53 +1,154 @@
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::fmt;
+use std::num::IntErrorKind;
 use std::str::FromStr;
-use serde::{Deserialize, Serialize};
+use config::{Source, Value};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use yaml_rust::Yaml;
+use yaml_rust::yaml::Hash;
 use crate::config::ConfigError;
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ByteSize(u64);
 
-const B: ByteSize = ByteSize(1);
-const KiB: ByteSize = ByteSize(B.0 << 10);
-const MiB: ByteSize = ByteSize(KiB.0 << 10);
-const GiB: ByteSize = ByteSize(MiB.0 << 10);
-const TiB: ByteSize = ByteSize(GiB.0 << 10);
-const PiB: ByteSize = ByteSize(TiB.0 << 10);
+pub const B: ByteSize = ByteSize(1);
+#[allow(non_upper_case_globals)]
+pub const KiB: ByteSize = ByteSize(B.0 << 10);
+#[allow(non_upper_case_globals)]
+pub const MiB: ByteSize = ByteSize(KiB.0 << 10);
+#[allow(non_upper_case_globals)]
+pub const GiB: ByteSize = ByteSize(MiB.0 << 10);
+#[allow(non_upper_case_globals)]
+pub const TiB: ByteSize = ByteSize(GiB.0 << 10);
+#[allow(non_upper_case_globals)]
+pub const PiB: ByteSize = ByteSize(TiB.0 << 10);
 
 impl ByteSize {
+    pub const fn from_bytes(n: u64) -> ByteSize {
+        ByteSize(n)
+    }
+
+    pub fn as_bytes(&self) -> u64 {
+        self.0
+    }
+
+    // Returns `None` when the sum doesn't fit in a u64.
+    pub fn checked_add(self, other: ByteSize) -> Option<ByteSize> {
+        self.0.checked_add(other.0).map(ByteSize)
+    }
+
+    // Floors at zero bytes.
+    pub fn saturating_sub(self, other: ByteSize) -> ByteSize {
+        ByteSize(self.0.saturating_sub(other.0))
+    }
+
+    /// Parses a number followed by an optional unit, with or without whitespace in between, so `4GiB`, `4 GiB` and
+    /// the output of `to_string` all parse.
     pub fn set(&mut self, s: &str) -> Result<(), ConfigError> {
-        let (digit_string, unit): (String, String) = s.chars().partition(|&c| c.is_digit(10));
-
-        let digits = digit_string.parse::<u64>().map_err(|_| ConfigError::Custom(s.to_string()))?;
-
-        match unit.to_uppercase().trim() {
-            "B" => *self = ByteSize(digits * B.0),
-            "KB" | "KIB" => *self = ByteSize(digits * KiB.0),
-            "MB" | "MIB" => *self = ByteSize(digits * MiB.0),
-            "GB" | "GIB" => *self = ByteSize(digits * GiB.0),
-            "TB" | "TIB" => *self = ByteSize(digits * TiB.0),
-            "PB" | "PIB" => *self = ByteSize(digits * PiB.0),
-            _ => *self = ByteSize(digits),
-        }
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+
+        // SI units (`KB`, `MB`, ...) are powers of 1000, IEC units (`KiB`, `MiB`, ...) powers of 1024.
+        let multiplier = match unit.trim().to_uppercase().as_str() {
+            "" | "B" => B.0,
+            "KB" => 1_000,
+            "MB" => 1_000_000,
+            "GB" => 1_000_000_000,
+            "TB" => 1_000_000_000_000,
+            "PB" => 1_000_000_000_000_000,
+            "KIB" => KiB.0,
+            "MIB" => MiB.0,
+            "GIB" => GiB.0,
+            "TIB" => TiB.0,
+            "PIB" => PiB.0,
+            _ => return Err(ConfigError::UnableToParseByteSize),
+        };
 
+        // Fractional values such as `1.5GiB` are rounded to the nearest byte.
+        let bytes = if number.contains('.') {
+            let value = number.parse::<f64>().map_err(|_| ConfigError::UnableToParseByteSize)?;
+            let bytes = (value * multiplier as f64).round();
+            if bytes >= u64::MAX as f64 {
+                return Err(ConfigError::ByteSizeOverflow(s.to_string()));
+            }
+            bytes as u64
+        } else {
+            number.parse::<u64>()
+                .map_err(|e| match e.kind() {
+                    IntErrorKind::PosOverflow => ConfigError::ByteSizeOverflow(s.to_string()),
+                    _ => ConfigError::UnableToParseByteSize,
+                })?
+                .checked_mul(multiplier)
+                .ok_or_else(|| ConfigError::ByteSizeOverflow(s.to_string()))?
+        };
+
+        *self = ByteSize(bytes);
         Ok(())
     }
+}
+
+const MAX_DISPLAY_DECIMALS: usize = 3;
 
-    pub fn to_string(&self) -> String {
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         const UNIT: u64 = 1024;
+
+        if self.0 < UNIT {
+            return pad(f, &format!("{} B", self.0));
+        }
+
         let mut div = UNIT;
         let mut exp = 0;
         let mut n = self.0 / UNIT;
 
-        while n >= UNIT {
+        // PiB is the largest unit, anything above is expressed as a multiple of it.
+        while n >= UNIT && exp < 4 {
             div *= UNIT;
             exp += 1;
             n /= UNIT;
         }
 
-        format!("{} {}iB", self.0 / div, "KMGTP".chars().nth(exp).unwrap_or(' '))
+        let unit = "KMGTP".chars().nth(exp).unwrap();
+        let value = self.0 as f64 / div as f64;
+        if let Some(precision) = f.precision() {
+            return pad(f, &format!("{:.*} {}iB", precision, value, unit));
+        }
+
+        // Without a precision the output parses back to the same size: the fewest decimals (up to 3) that do, or
+        // the plain byte count when none do.
+        let exact = (0..=MAX_DISPLAY_DECIMALS)
+            .map(|decimals| format!("{:.*} {}iB", decimals, value, unit))
+            .find(|s| ByteSize::from_str(s).ok() == Some(*self))
+            .unwrap_or_else(|| format!("{} B", self.0));
+
+        pad(f, &exact)
     }
 }
 
+// Applies the formatter's width, fill and alignment to `s`. Precision is used by
+// `ByteSize` for the number of decimals, so `Formatter::pad` can't be used as it
+// would truncate the string instead.
+fn pad(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    let len = s.chars().count();
+    let padding = f.width().unwrap_or(0).saturating_sub(len);
+    if padding == 0 {
+        return f.write_str(s);
+    }
+
+    let (pre, post) = match f.align() {
+        Some(fmt::Alignment::Right) => (padding, 0),
+        Some(fmt::Alignment::Center) => (padding / 2, padding - padding / 2),
+        _ => (0, padding),
+    };
+
+    let fill = f.fill().to_string();
+    f.write_str(&fill.repeat(pre))?;
+    f.write_str(s)?;
+    f.write_str(&fill.repeat(post))
+}
+
 impl FromStr for ByteSize {
     type Err = ConfigError;
 
@@ -58,22 +159,159 @@ impl FromStr for ByteSize {
     }
 }
 
-pub fn expand_home_dir(path: &str) -> Result<PathBuf, String> {
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ByteSizeVisitor;
+
+        impl<'de> Visitor<'de> for ByteSizeVisitor {
+            type Value = ByteSize;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a byte size such as \"1GiB\" or a number of bytes")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<ByteSize, E> {
+                Ok(ByteSize(v))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<ByteSize, E> {
+                u64::try_from(v)
+                    .map(ByteSize)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<ByteSize, E> {
+                ByteSize::from_str(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(ByteSizeVisitor)
+    }
+}
+
+/// Deserializes either a list of strings or a single comma-separated string.
+pub fn deserialize_string_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    struct StringListVisitor;
+
+    impl<'de> Visitor<'de> for StringListVisitor {
+        type Value = Vec<String>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a list of strings or a comma-separated string")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Vec<String>, E> {
+            Ok(v.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<String>, A::Error> {
+            let mut items = Vec::new();
+            while let Some(item) = seq.next_element::<String>()? {
+                items.push(item.trim().to_string());
+            }
+            Ok(items)
+        }
+    }
+
+    deserializer.deserialize_any(StringListVisitor)
+}
+
+/// The environment variables starting with `<prefix>_` as config values. A variable sets the key whose path it spells
+/// with every `.` and `_` written as `_`, e.g. `DEFRA_NET_P2P_DISABLED` sets `net.p2p_disabled` and
+/// `DEFRA_DATASTORE_BADGER_PATH` sets `datastore.badger.path`. Variables that don't spell a known key are ignored.
+#[derive(Debug, Clone)]
+pub struct EnvSource {
+    prefix: String,
+    // The lowercase variable names without the prefix, mapped to the keys they set.
+    keys: HashMap<String, String>,
+}
+
+impl EnvSource {
+    pub fn new(prefix: &str, keys: impl IntoIterator<Item = String>) -> Self {
+        EnvSource {
+            prefix: format!("{}_", prefix.to_lowercase()),
+            keys: keys.into_iter().map(|key| (key.replace('.', "_"), key)).collect(),
+        }
+    }
+}
+
+impl Source for EnvSource {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<HashMap<String, Value>, config::ConfigError> {
+        let origin = "the environment".to_string();
+        Ok(std::env::vars()
+            .filter_map(|(name, value)| {
+                let key = self.keys.get(name.to_lowercase().strip_prefix(&self.prefix)?)?;
+                Some((key.clone(), Value::new(Some(&origin), value)))
+            })
+            .collect())
+    }
+}
+
+/// Returns the dotted paths of the values in `value`, e.g. `badger.path`. Arrays are values, empty objects have none.
+pub fn leaf_keys(value: &serde_json::Value) -> Vec<String> {
+    match value {
+        serde_json::Value::Object(map) => map
+            .iter()
+            .flat_map(|(key, value)| match value {
+                serde_json::Value::Object(_) => leaf_keys(value).into_iter().map(|leaf| format!("{}.{}", key, leaf)).collect(),
+                _ => vec![key.clone()],
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+pub fn json_to_yaml(value: serde_json::Value) -> Yaml {
+    match value {
+        serde_json::Value::Null => Yaml::Null,
+        serde_json::Value::Bool(b) => Yaml::Boolean(b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        serde_json::Value::String(s) => Yaml::String(s),
+        serde_json::Value::Array(items) => Yaml::Array(items.into_iter().map(json_to_yaml).collect()),
+        serde_json::Value::Object(map) => {
+            let mut hash = Hash::new();
+            for (k, v) in map {
+                hash.insert(Yaml::String(k), json_to_yaml(v));
+            }
+            Yaml::Hash(hash)
+        }
+    }
+}
+
+/// Replaces a leading `~/` in `path` with the user's home directory.
+pub fn expand_home_dir(path: &mut String) -> Result<(), ConfigError> {
     if path == "~" {
-        return Err("Path cannot be home directory.".to_string());
-    } else if path.starts_with("~/") {
-        let home_dir = dirs::home_dir().ok_or("Unable to get home directory.".to_string())?;
-        return Ok(home_dir.join(&path[2..]));
+        return Err(ConfigError::PathCannotBeHomeDir);
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        let home_dir = dirs::home_dir().ok_or(ConfigError::UnableToExpandHomeDir)?;
+        *path = home_dir.join(rest).to_string_lossy().into_owned();
     }
 
-    Ok(Path::new(path).to_path_buf())
+    Ok(())
 }
 
-fn is_lowercase_alpha(s: &str) -> bool {
+pub fn is_lowercase_alpha(s: &str) -> bool {
     s.chars().all(|c| c.is_ascii_lowercase())
 }
 
-fn parse_kv(kv: &str) -> Result<(String, String), String> {
+pub fn parse_kv(kv: &str) -> Result<(String, String), String> {
     let mut parts = kv.splitn(2, '=');
     let key = parts.next().unwrap_or_default().to_string();
     let value = parts.next().unwrap_or_default().to_string();
@@ -83,4 +321,126 @@ fn parse_kv(kv: &str) -> Result<(String, String), String> {
     }
 
     Ok((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_size_to_string_parses_back() {
+        for size in [0, 512, 1024, 1536, 1025, 4 * GiB.0, 3 * PiB.0 / 2, 1024 * PiB.0, u64::MAX] {
+            let size = ByteSize(size);
+            assert_eq!(ByteSize::from_str(&size.to_string()).unwrap(), size, "{}", size);
+        }
+        assert_eq!(ByteSize::from_str("4GiB").unwrap(), ByteSize::from_str("4 GiB").unwrap());
+    }
+
+    #[test]
+    fn byte_size_to_string_keeps_fractions() {
+        assert_eq!(ByteSize(1536).to_string(), "1.5 KiB");
+        assert_eq!(ByteSize(5 * MiB.0 / 4).to_string(), "1.25 MiB");
+        assert_eq!((GiB.checked_add(B).unwrap()).to_string(), "1073741825 B");
+        assert_eq!(format!("{:.2}", ByteSize(1536)), "1.50 KiB");
+    }
+
+    #[test]
+    fn byte_size_to_string_switches_units_at_1024() {
+        assert_eq!(ByteSize(0).to_string(), "0 B");
+        assert_eq!(ByteSize(1).to_string(), "1 B");
+        assert_eq!(ByteSize(1023).to_string(), "1023 B");
+        assert_eq!(ByteSize(1024).to_string(), "1 KiB");
+        assert_eq!(ByteSize(1536).to_string(), "1.5 KiB");
+    }
+
+    #[test]
+    fn byte_sizes_sort_by_their_number_of_bytes() {
+        let mut sizes = vec![GiB, ByteSize(1), KiB, ByteSize(0), MiB, ByteSize(1023)];
+        sizes.sort();
+
+        assert_eq!(sizes, vec![ByteSize(0), ByteSize(1), ByteSize(1023), KiB, MiB, GiB]);
+        assert_eq!(sizes.iter().map(|size| size.as_bytes()).max(), Some(1 << 30));
+        assert!(KiB < MiB);
+    }
+
+    #[test]
+    fn byte_size_parses_fractional_values() {
+        assert_eq!(ByteSize::from_str("1.5KiB").unwrap(), ByteSize(1536));
+        assert_eq!(ByteSize::from_str("0.5 MiB").unwrap(), ByteSize(512 * 1024));
+        assert_eq!(ByteSize::from_str("1.5").unwrap(), ByteSize(2));
+
+        for invalid in ["1.5.5KiB", "1.5KiBs", "1.5 KiB 2", "", "KiB", "-1KiB"] {
+            assert!(matches!(ByteSize::from_str(invalid), Err(ConfigError::UnableToParseByteSize)), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn byte_size_overflow_is_an_error() {
+        for huge in ["16384PiB", "99999999999PiB", "16384.5 PiB", "18446744073709551616"] {
+            assert!(matches!(ByteSize::from_str(huge), Err(ConfigError::ByteSizeOverflow(_))), "{}", huge);
+        }
+        assert_eq!(ByteSize::from_str("16383PiB").unwrap(), ByteSize(16383 * PiB.0));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Sized {
+        size: ByteSize,
+    }
+
+    #[test]
+    fn byte_size_round_trips_through_json_and_yaml() {
+        let sized = Sized { size: ByteSize(1536) };
+
+        let json = serde_json::to_string(&sized).unwrap();
+        assert_eq!(json, r#"{"size":"1.5 KiB"}"#);
+        assert_eq!(serde_json::from_str::<Sized>(&json).unwrap(), sized);
+        assert_eq!(serde_json::from_str::<Sized>(r#"{"size":1536}"#).unwrap(), sized);
+
+        let mut yaml = String::new();
+        yaml_rust::YamlEmitter::new(&mut yaml).dump(&json_to_yaml(serde_json::to_value(&sized).unwrap())).unwrap();
+        let mut config = config::Config::default();
+        config.merge(config::File::from_str(&yaml, config::FileFormat::Yaml)).unwrap();
+        assert_eq!(config.try_into::<Sized>().unwrap(), sized);
+
+        let mut config = config::Config::default();
+        config.merge(config::File::from_str("size: 1536", config::FileFormat::Yaml)).unwrap();
+        assert_eq!(config.try_into::<Sized>().unwrap(), sized);
+    }
+
+    #[test]
+    fn expand_home_dir_rejects_the_home_dir_itself() {
+        let mut path = "~".to_string();
+        assert!(matches!(expand_home_dir(&mut path), Err(ConfigError::PathCannotBeHomeDir)));
+        assert_eq!(path, "~");
+
+        let mut path = "~user/data".to_string();
+        expand_home_dir(&mut path).unwrap();
+        assert_eq!(path, "~user/data");
+    }
+
+    #[test]
+    fn byte_size_arithmetic() {
+        assert_eq!(ByteSize::from_bytes(1536).as_bytes(), 1536);
+        assert_eq!(KiB.checked_add(ByteSize::from_bytes(512)), Some(ByteSize(1536)));
+        assert_eq!(ByteSize::from_bytes(u64::MAX).checked_add(B), None);
+        assert_eq!(MiB.saturating_sub(KiB), ByteSize(MiB.0 - KiB.0));
+        assert_eq!(KiB.saturating_sub(MiB), ByteSize(0));
+    }
+
+    #[test]
+    fn si_units_are_powers_of_1000_and_iec_units_of_1024() {
+        assert_eq!(ByteSize::from_str("1GB").unwrap().as_bytes(), 1_000_000_000);
+        assert_eq!(ByteSize::from_str("1GiB").unwrap().as_bytes(), 1_073_741_824);
+        assert_eq!(ByteSize::from_str("2kb").unwrap().as_bytes(), 2_000);
+        assert_eq!(ByteSize::from_str("2KiB").unwrap().as_bytes(), 2_048);
+        assert_eq!(ByteSize::from_str("1GB").unwrap().to_string(), "1000000000 B");
+    }
+
+    #[test]
+    fn byte_size_display_honours_width_and_precision() {
+        assert_eq!(format!("{}", ByteSize(4 * GiB.0)), "4 GiB");
+        assert_eq!(format!("{:>10}", ByteSize(4 * GiB.0)), "     4 GiB");
+        assert_eq!(format!("{:<8}|", ByteSize(0)), "0 B     |");
+        assert_eq!(format!("{:^9.1}", ByteSize(1536)), " 1.5 KiB ");
+    }
 }
\ No newline at end of file
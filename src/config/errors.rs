@@ -33,13 +33,16 @@ pub enum ConfigError {
     InvalidRPCMaxConnectionIdle(String),
 
     #[error("invalid P2P address: {0}, {1}")]
-    InvalidP2PAddress(String, String),
+    InvalidP2PAddress(#[source] multiaddr::Error, String),
 
     #[error("invalid RPC address: {0}")]
     InvalidRPCAddress(String),
 
     #[error("invalid bootstrap peers: {0}, {1}")]
-    InvalidBootstrapPeers(String, String),
+    InvalidBootstrapPeers(#[source] multiaddr::Error, String),
+
+    #[error("contradictory net config: {0}")]
+    ContradictoryNetConfig(String),
 
     #[error("invalid log level: {0}")]
     InvalidLogLevel(String),
@@ -47,6 +50,12 @@ pub enum ConfigError {
     #[error("invalid store type: {0}")]
     InvalidDatastoreType(String),
 
+    #[error("invalid memory store size: {0} bytes, must be at least 1MiB")]
+    InvalidMemorySize(u64),
+
+    #[error("invalid max transaction retries: {0}, must be between 0 and 100")]
+    InvalidMaxTxnRetries(i32),
+
     #[error("invalid override config for {0}")]
     OverrideConfigConvertFailed(String),
 
@@ -54,13 +63,13 @@ pub enum ConfigError {
     InvalidLogFormat(String),
 
     #[error("failed to marshal Config to JSON")]
-    ConfigToJSONFailed,
+    ConfigToJSONFailed(#[source] serde_json::Error),
 
     #[error("invalid named logger name: {0}")]
     InvalidNamedLoggerName(String),
 
-    #[error("could not process config template")]
-    ConfigTemplateFailed,
+    #[error("could not process config template: {0}")]
+    ConfigTemplateFailed(String),
 
     #[error("could not get named logger config: {0}")]
     CouldNotObtainLoggerConfig(String, String),
@@ -81,14 +90,20 @@ pub enum ConfigError {
     DuplicateLoggerName(String),
 
     #[error("failed to read config")]
-    ReadingConfigFile,
+    ReadingConfigFile(#[source] ::config::ConfigError),
 
     #[error("failed to load config")]
-    LoadingConfig,
+    LoadingConfig(#[source] ::config::ConfigError),
+
+    #[error("failed to parse config at line {line}, column {col}: {msg}")]
+    ParseAt { line: usize, col: usize, msg: String },
 
     #[error("unable to parse byte size")]
     UnableToParseByteSize,
 
+    #[error("byte size overflows: {0}")]
+    ByteSizeOverflow(String),
+
     #[error("invalid logger config: {0}")]
     InvalidLoggerConfig(String),
 
@@ -101,9 +116,27 @@ pub enum ConfigError {
     #[error("cannot provide port with domain name")]
     NoPortWithDomain,
 
+    #[error("missing TLS certificate: {0}")]
+    MissingTLSCertificate(String),
+
+    #[error("invalid email: {0}")]
+    InvalidEmail(String),
+
+    #[error("invalid allowed origin: {0}")]
+    InvalidAllowedOrigin(String),
+
     #[error("invalid root directory: {0}")]
     InvalidRootDir(String),
 
+    #[error("failed to watch config file")]
+    Watch(#[source] notify::Error),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("config error: {0}")]
+    Config(#[source] ::config::ConfigError),
+
     #[error("custom error: {0}")]
     Custom(String),
 }
@@ -1,8 +1,12 @@
+#[allow(clippy::module_inception)]
 pub mod config;
 pub mod errors;
 
 mod config_utils;
 mod config_file;
+#[cfg(any(test, feature = "test-utils"))]
+mod test_utils;
 
 pub use errors::ConfigError;
-pub use config::Config;
\ No newline at end of file
+pub use config::Config;
+pub use config_file::WatchHandle;
\ No newline at end of file
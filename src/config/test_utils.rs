@@ -0,0 +1,86 @@
+#[cfg(test)]
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use tempfile::TempDir;
+
+use super::Config;
+
+impl Config {
+    /// Returns the default config rooted in a fresh temp directory, using the memory datastore, so tests never
+    /// touch `~/.defradb`. The directory is removed when the returned `TempDir` is dropped, keep it alive for as
+    /// long as the config is used.
+    pub fn clone_for_test() -> (Config, TempDir) {
+        let dir = TempDir::new().expect("failed to create a temp dir");
+        let mut cfg = Config::default_config().expect("failed to build the default config");
+        cfg.set_rootdir(&dir.path().to_string_lossy()).expect("failed to set the rootdir");
+        cfg.datastore.store = "memory".to_string();
+        cfg.config.set_default("datastore.store", "memory").expect("failed to select the memory datastore");
+        (cfg, dir)
+    }
+}
+
+/// Serializes the tests that read or set environment variables, the config picks up every `DEFRA_*` variable.
+/// The variables set through the returned guard are removed when it is dropped.
+#[cfg(test)]
+pub(crate) fn env_lock() -> EnvGuard {
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+    EnvGuard { _lock: ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner), vars: Vec::new() }
+}
+
+#[cfg(test)]
+pub(crate) struct EnvGuard {
+    _lock: MutexGuard<'static, ()>,
+    vars: Vec<String>,
+}
+
+#[cfg(test)]
+impl EnvGuard {
+    pub(crate) fn set(&mut self, name: &str, value: &str) {
+        std::env::set_var(name, value);
+        self.vars.push(name.to_string());
+    }
+}
+
+#[cfg(test)]
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        for name in &self.vars {
+            std::env::remove_var(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn clone_for_test_writes_and_reads_back_within_the_temp_dir() {
+        let _env = env_lock();
+        let (mut cfg, dir) = Config::clone_for_test();
+        assert!(Path::new(&cfg.config_file_path()).starts_with(dir.path().canonicalize().unwrap()));
+
+        cfg.api.address = "localhost:1234".to_string();
+        cfg.write_config_file(false).unwrap();
+
+        let mut loaded = Config::default_config().unwrap();
+        loaded.rootdir = cfg.rootdir.clone();
+        loaded.load_with_rootdir(true).unwrap();
+        assert_eq!(loaded.api.address, "localhost:1234");
+        assert_eq!(loaded.datastore.store, "memory");
+    }
+
+    #[test]
+    fn clone_for_test_removes_the_dir_on_drop() {
+        let _env = env_lock();
+        let (cfg, dir) = Config::clone_for_test();
+        let (other, _other_dir) = Config::clone_for_test();
+        assert_ne!(cfg.rootdir, other.rootdir);
+
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
@@ -4,5 +4,46 @@ use thiserror::Error;
 pub enum BadgerError {
 
     #[error("invalid order type: {0}")]
-    InvalidOrderType(String)
+    InvalidOrderType(String),
+
+    #[error("key not found: {0:?}")]
+    KeyNotFound(Vec<u8>),
+
+    #[error("transaction conflict, please retry")]
+    TxnConflict,
+
+    #[error("transaction is read-only")]
+    TxnReadOnly,
+
+    #[error("database is closed")]
+    DbClosed,
+
+    #[error("corrupt data: {0}")]
+    Corrupt(String),
+
+    #[error("badger datastore is not available")]
+    Unavailable,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastore::Error;
+
+    #[test]
+    fn every_variant_formats() {
+        let cases = [
+            (BadgerError::InvalidOrderType("up".to_string()), "invalid order type: up"),
+            (BadgerError::KeyNotFound(b"k".to_vec()), "key not found: [107]"),
+            (BadgerError::TxnConflict, "transaction conflict, please retry"),
+            (BadgerError::TxnReadOnly, "transaction is read-only"),
+            (BadgerError::DbClosed, "database is closed"),
+            (BadgerError::Corrupt("bad crc".to_string()), "corrupt data: bad crc"),
+            (BadgerError::Unavailable, "badger datastore is not available"),
+        ];
+        for (err, expected) in cases {
+            let boxed: Box<dyn Error> = Box::new(err);
+            assert_eq!(boxed.to_string(), expected);
+        }
+    }
 }
\ No newline at end of file
@@ -1,3 +1,3 @@
-mod errors;
+pub mod errors;
 mod iterator;
 mod datastore;
\ No newline at end of file
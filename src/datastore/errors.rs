@@ -0,0 +1,52 @@
+use thiserror::Error;
+
+use crate::datastore::badger::v4::errors::BadgerError;
+use crate::datastore::memory::MemoryError;
+
+/// The errors returned by the datastores, backend specific failures are wrapped in `Backend`.
+#[derive(Error, Debug)]
+pub enum DatastoreError {
+
+    #[error("key not found: {0:?}")]
+    NotFound(Vec<u8>),
+
+    #[error("transaction conflict, please retry")]
+    Conflict,
+
+    #[error("transaction is read-only")]
+    ReadOnly,
+
+    #[error("datastore is closed")]
+    Closed,
+
+    #[error("datastore is out of space, its capacity is {capacity} bytes")]
+    OutOfSpace { capacity: u64 },
+
+    #[error("datastore io error")]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<MemoryError> for DatastoreError {
+    fn from(err: MemoryError) -> Self {
+        match err {
+            MemoryError::StoreClosed => DatastoreError::Closed,
+            MemoryError::TxnReadOnly => DatastoreError::ReadOnly,
+            err => DatastoreError::Backend(Box::new(err)),
+        }
+    }
+}
+
+impl From<BadgerError> for DatastoreError {
+    fn from(err: BadgerError) -> Self {
+        match err {
+            BadgerError::KeyNotFound(key) => DatastoreError::NotFound(key),
+            BadgerError::TxnConflict => DatastoreError::Conflict,
+            BadgerError::TxnReadOnly => DatastoreError::ReadOnly,
+            BadgerError::DbClosed => DatastoreError::Closed,
+            err => DatastoreError::Backend(Box::new(err)),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::datastore::{Store, Txn, StoreData, DataQuery, Error, KeyValuePair};
+use std::result::Result;
+
+pub trait Iterable {
+    fn get_iterator(&self, query: DataQuery) -> Result<Box<dyn Iterator>, Box<dyn Error>>;
+
+    /// Counts the keys in the half-open range `[start_prefix, end_prefix)`, an empty `end_prefix` counts to the end
+    /// of the keyspace. Stores should override this to avoid reading the values.
+    fn count_prefix(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<usize, Box<dyn Error>> {
+        let mut count = 0;
+        walk_keys(self, start_prefix, end_prefix, |_| count += 1)?;
+        Ok(count)
+    }
+
+    /// Returns the keys in the half-open range `[start_prefix, end_prefix)` in ascending order, an empty `end_prefix`
+    /// lists to the end of the keyspace. Stores should override this to avoid reading the values.
+    fn iterate_keys_only(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        let mut keys = Vec::new();
+        walk_keys(self, start_prefix, end_prefix, |key| keys.push(key))?;
+        Ok(keys)
+    }
+}
+
+// Calls `f` with every key in `[start, end)` in ascending order. The range is walked with `seek` and `next_kv`, which
+// take the bounds as bytes, so it works for keys that aren't UTF-8.
+fn walk_keys<I: Iterable + ?Sized>(iterable: &I, start: &[u8], end: &[u8], mut f: impl FnMut(Vec<u8>)) -> Result<(), Box<dyn Error>> {
+    let mut iter = iterable.get_iterator(DataQuery::new())?;
+    iter.seek(start)?;
+    while let Some((key, _)) = iter.next_kv()? {
+        if !end.is_empty() && key.as_slice() >= end {
+            break;
+        }
+        f(key);
+    }
+    iter.close()
+}
+
+pub trait Iterator {
+    /// Returns the entries with keys in the half-open range `[start_prefix, end_prefix)`, in the order of the query
+    /// the iterator was created with.
+    /// An empty `end_prefix` iterates to the end of the keyspace.
+    fn iterate_prefix(&self, start_prefix: String, end_prefix: String) -> Result<Vec<Box<dyn StoreData>>, Box<dyn Error>>;
+    /// Repositions the cursor to `key`, later iterations start at the first key at or past `key` in the order of
+    /// the query, i.e. the first key >= `key` when ascending and the first key <= `key` when descending.
+    fn seek(&mut self, key: &[u8]) -> Result<(), Box<dyn Error>>;
+    /// Returns the next entry of the query, or `None` once all of them were returned. Unlike `iterate_prefix` this
+    /// streams one entry at a time, seeking restarts the stream at the new cursor.
+    fn next_kv(&mut self) -> Result<Option<KeyValuePair>, Box<dyn Error>>;
+    fn close(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+pub trait IterableTxn: Txn + Iterable {}
+
+pub trait IterableDatastore: Store + Iterable {
+    /// Deletes every key starting with `prefix` and returns how many were deleted.
+    fn delete_prefix(&self, prefix: &[u8]) -> Result<usize, Box<dyn Error>> {
+        let mut iter = self.get_iterator(DataQuery::new().prefix(prefix))?;
+        let entries = iter.iterate_prefix(String::new(), String::new())?;
+        iter.close()?;
+
+        for entry in &entries {
+            self.delete(entry.key())?;
+        }
+        Ok(entries.len())
+    }
+}
+
+pub trait IterableTxnDatastore: Store {
+    fn new_iterable_transaction(&self, read_only: bool) -> Result<Box<dyn IterableTxn + '_>, Box<dyn Error>>;
+
+    /// Returns a new handle to the same underlying store.
+    fn clone_box(&self) -> Box<dyn IterableTxnDatastore>;
+}
+
+impl Clone for Box<dyn IterableTxnDatastore> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastore::Write;
+    use crate::datastore::memory::MemoryStore;
+
+    // Only implements `get_iterator`, so the default methods of `Iterable` are used.
+    struct DefaultsOnly(MemoryStore);
+
+    impl Iterable for DefaultsOnly {
+        fn get_iterator(&self, query: DataQuery) -> Result<Box<dyn Iterator>, Box<dyn Error>> {
+            self.0.get_iterator(query)
+        }
+    }
+
+    fn store_with(keys: &[&[u8]]) -> DefaultsOnly {
+        let store = MemoryStore::new();
+        for key in keys {
+            store.put(key, b"v").unwrap();
+        }
+        DefaultsOnly(store)
+    }
+
+    #[test]
+    fn default_count_prefix_compares_bounds_as_bytes() {
+        let store = store_with(&[b"a", b"\xfe", b"\xff\x00", b"\xff\x01", b"\xff\xff"]);
+        assert_eq!(store.count_prefix(b"", b"").unwrap(), 5);
+        assert_eq!(store.count_prefix(b"\xff\x00", b"\xff\xff").unwrap(), 2);
+        assert_eq!(store.count_prefix(b"\xfe", b"\xff").unwrap(), 1);
+        assert_eq!(store.count_prefix(b"\xff", b"a").unwrap(), 0);
+    }
+
+    #[test]
+    fn default_keys_only_iteration_keeps_binary_keys_intact() {
+        let store = store_with(&[b"\xff", b"\xff\x00", b"\xff\x00\x80", b"\xff\x01"]);
+        assert_eq!(store.iterate_keys_only(b"\xff\x00", b"\xff\x01").unwrap(), vec![b"\xff\x00".to_vec(), b"\xff\x00\x80".to_vec()]);
+        assert_eq!(store.iterate_keys_only(b"\xff\x00\x80", b"").unwrap(), vec![b"\xff\x00\x80".to_vec(), b"\xff\x01".to_vec()]);
+    }
+}
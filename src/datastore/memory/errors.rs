@@ -0,0 +1,23 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MemoryError {
+
+    #[error("memory store lock poisoned")]
+    LockPoisoned,
+
+    #[error("memory store is closed")]
+    StoreClosed,
+
+    #[error("iterator is closed")]
+    IteratorClosed,
+
+    #[error("transaction is read-only")]
+    TxnReadOnly,
+
+    #[error("transaction has already been committed or discarded")]
+    TxnFinished,
+
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+}
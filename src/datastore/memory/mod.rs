@@ -0,0 +1,920 @@
+mod errors;
+mod txn;
+
+use std::collections::{btree_map, BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write as _};
+use std::ops::Bound;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+use crate::datastore::{Store, Read, Write, Batch, StoreData, DataQuery, DatastoreError, Error, KeyValue, KeyValuePair, Order};
+use crate::datastore::iterable::{self, Iterable, IterableDatastore, IterableTxn, IterableTxnDatastore};
+
+pub use errors::MemoryError;
+pub use txn::MemoryTxn;
+
+type KeyValues = BTreeMap<Vec<u8>, Vec<u8>>;
+type Versions = HashMap<Vec<u8>, u64>;
+
+#[derive(Debug, Default)]
+struct State {
+    values: KeyValues,
+    expiries: HashMap<Vec<u8>, Instant>,
+    // The version of the last write to every key, taken from a counter bumped on every write.
+    versions: Versions,
+    last_version: u64,
+    // The sum of the key and value lengths of all entries.
+    used: u64,
+    capacity: Option<u64>,
+    closed: bool,
+}
+
+impl State {
+    fn is_expired(&self, key: &[u8]) -> bool {
+        self.expiries.get(key).is_some_and(|expiry| *expiry <= Instant::now())
+    }
+
+    fn has_expired(&self) -> bool {
+        let now = Instant::now();
+        self.expiries.values().any(|expiry| *expiry <= now)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&Vec<u8>> {
+        if self.is_expired(key) {
+            return None;
+        }
+        self.values.get(key)
+    }
+
+    // Fails when the store would grow past its capacity, replacing an entry only counts the difference in size.
+    fn ensure_space(&self, freed: u64, added: u64) -> Result<(), DatastoreError> {
+        match self.capacity {
+            Some(capacity) if self.used - freed + added > capacity => Err(DatastoreError::OutOfSpace { capacity }),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry_size(&self, key: &[u8]) -> u64 {
+        self.values.get(key).map_or(0, |value| entry_size(key, value))
+    }
+
+    fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), DatastoreError> {
+        self.ensure_space(self.entry_size(&key), entry_size(&key, &value))?;
+        self.insert(key, value);
+        Ok(())
+    }
+
+    // Callers check the capacity first.
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.expiries.remove(&key);
+        let freed = self.entry_size(&key);
+        self.used = self.used - freed + entry_size(&key, &value);
+        self.last_version += 1;
+        self.versions.insert(key.clone(), self.last_version);
+        self.values.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.expiries.remove(key);
+        self.versions.remove(key);
+        if let Some(old) = self.values.remove(key) {
+            self.used -= entry_size(key, &old);
+        }
+    }
+
+    // Splits the keys starting with `prefix` off the map instead of removing them one by one.
+    fn remove_prefix(&mut self, prefix: &[u8]) -> usize {
+        let mut removed = self.values.split_off(prefix);
+        if let Some(after) = removed.keys().find(|key| !key.starts_with(prefix)).cloned() {
+            self.values.append(&mut removed.split_off(&after));
+        }
+        for (key, value) in &removed {
+            self.expiries.remove(key);
+            self.versions.remove(key);
+            self.used -= entry_size(key, value);
+        }
+        removed.len()
+    }
+
+    // Applies all operations, or none of them if they don't fit.
+    fn apply(&mut self, ops: Vec<BatchOp>) -> Result<(), DatastoreError> {
+        // Only the last operation on a key decides its size.
+        let mut last: HashMap<&[u8], u64> = HashMap::new();
+        for op in &ops {
+            match op {
+                BatchOp::Put(key, value) => last.insert(key, entry_size(key, value)),
+                BatchOp::Delete(key) => last.insert(key, 0),
+            };
+        }
+        let freed = last.keys().map(|key| self.entry_size(key)).sum();
+        self.ensure_space(freed, last.values().sum())?;
+
+        for op in ops {
+            match op {
+                BatchOp::Put(key, value) => self.insert(key, value),
+                BatchOp::Delete(key) => self.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    fn remove_expired(&mut self) {
+        let now = Instant::now();
+        let values = &mut self.values;
+        let versions = &mut self.versions;
+        let used = &mut self.used;
+        self.expiries.retain(|key, expiry| {
+            let live = *expiry > now;
+            if !live {
+                versions.remove(key);
+                if let Some(value) = values.remove(key) {
+                    *used -= entry_size(key, &value);
+                }
+            }
+            live
+        });
+    }
+}
+
+/// An in-memory datastore that keeps its keys sorted.
+/// Keys written with a TTL are removed lazily, the next time the store is accessed after they expire.
+/// Clones share the same underlying map, closing one of them closes all of them.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    state: Arc<RwLock<State>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a store holding at most `capacity` bytes, counting the length of every key and value. Writes that
+    /// would exceed it fail with `DatastoreError::OutOfSpace` and leave the store unchanged.
+    pub fn with_capacity(capacity: u64) -> Self {
+        let state = State { capacity: Some(capacity), ..State::default() };
+        MemoryStore { state: Arc::new(RwLock::new(state)) }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.read().map_or(true, |state| state.closed)
+    }
+
+    /// Writes every key and value to `path`. Expiries and versions aren't part of the snapshot, keys written with a
+    /// TTL are restored without one and the restored keys are versioned in key order.
+    pub fn snapshot(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let state = self.read()?;
+
+        // The number of entries followed by the entries, each as the length of the key, the key, the length of the
+        // value and the value. All lengths are little endian u64.
+        let mut out = BufWriter::new(File::create(path).map_err(DatastoreError::from)?);
+        let mut write_all = |bytes: &[u8]| out.write_all(bytes).map_err(DatastoreError::from);
+
+        write_all(&(state.values.len() as u64).to_le_bytes())?;
+        for (key, value) in &state.values {
+            write_all(&(key.len() as u64).to_le_bytes())?;
+            write_all(key)?;
+            write_all(&(value.len() as u64).to_le_bytes())?;
+            write_all(value)?;
+        }
+        out.flush().map_err(DatastoreError::from)?;
+        Ok(())
+    }
+
+    /// Opens a new store with the contents of a snapshot written by `snapshot`.
+    pub fn restore(path: &Path) -> Result<MemoryStore, Box<dyn Error>> {
+        let data = fs::read(path).map_err(DatastoreError::from)?;
+        let mut rest = data.as_slice();
+
+        let count = read_u64(&mut rest)?;
+        let mut values = KeyValues::new();
+        for _ in 0..count {
+            let key_len = read_u64(&mut rest)?;
+            let key = read_bytes(&mut rest, key_len)?;
+            let value_len = read_u64(&mut rest)?;
+            let value = read_bytes(&mut rest, value_len)?;
+            values.insert(key, value);
+        }
+        if !rest.is_empty() {
+            return Err(DatastoreError::from(MemoryError::InvalidSnapshot("trailing data".to_string())).into());
+        }
+
+        let used = values.iter().map(|(key, value)| entry_size(key, value)).sum();
+        let versions: Versions = values.keys().cloned().zip(1..).collect();
+        let last_version = versions.len() as u64;
+        let state = State { values, versions, last_version, used, ..State::default() };
+        Ok(MemoryStore { state: Arc::new(RwLock::new(state)) })
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, State>, DatastoreError> {
+        let state = self.state.read().map_err(|_| MemoryError::LockPoisoned)?;
+        if state.closed {
+            return Err(DatastoreError::Closed);
+        }
+        if !state.has_expired() {
+            return Ok(state);
+        }
+
+        // Expired keys can only be removed under the write lock.
+        drop(state);
+        drop(self.write()?);
+        Ok(self.state.read().map_err(|_| MemoryError::LockPoisoned)?)
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_, State>, DatastoreError> {
+        let mut state = self.state.write().map_err(|_| MemoryError::LockPoisoned)?;
+        if state.closed {
+            return Err(DatastoreError::Closed);
+        }
+        state.remove_expired();
+        Ok(state)
+    }
+
+    // Applies all operations under a single write lock, or none of them if they don't fit.
+    fn apply(&self, ops: Vec<BatchOp>) -> Result<(), DatastoreError> {
+        self.write()?.apply(ops)
+    }
+
+    // Like `apply`, but fails with `DatastoreError::Conflict` if any of the keys no longer has the version in
+    // `touched`, `None` standing for a missing key. Both happen under the same write lock.
+    fn apply_if_unchanged(&self, touched: &HashMap<Vec<u8>, Option<u64>>, ops: Vec<BatchOp>) -> Result<(), DatastoreError> {
+        let mut state = self.write()?;
+        if touched.iter().any(|(key, version)| state.versions.get(key) != version.as_ref()) {
+            return Err(DatastoreError::Conflict);
+        }
+        state.apply(ops)
+    }
+}
+
+impl Read for MemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        Ok(self.read()?.get(key).cloned())
+    }
+
+    fn has(&self, key: &[u8]) -> Result<bool, Box<dyn Error>> {
+        Ok(self.read()?.get(key).is_some())
+    }
+
+    fn get_size(&self, key: &[u8]) -> Result<Option<usize>, Box<dyn Error>> {
+        Ok(self.read()?.get(key).map(Vec::len))
+    }
+}
+
+impl Write for MemoryStore {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.write()?.put(key.to_vec(), value.to_vec())?;
+        Ok(())
+    }
+
+    fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> Result<(), Box<dyn Error>> {
+        let mut state = self.write()?;
+        state.put(key.to_vec(), value.to_vec())?;
+        state.expiries.insert(key.to_vec(), Instant::now() + ttl);
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.write()?.remove(key);
+        Ok(())
+    }
+
+    fn cas(&self, key: &[u8], expected: Option<&[u8]>, new: &[u8]) -> Result<bool, Box<dyn Error>> {
+        let mut state = self.write()?;
+        if state.get(key).map(Vec::as_slice) != expected {
+            return Ok(false);
+        }
+        state.put(key.to_vec(), new.to_vec())?;
+        Ok(true)
+    }
+}
+
+impl Store for MemoryStore {
+    fn batch(&self) -> Result<Box<dyn Batch + '_>, Box<dyn Error>> {
+        Ok(Box::new(MemoryBatch { store: self, ops: Vec::new() }))
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        *self.state.write().map_err(|_| DatastoreError::from(MemoryError::LockPoisoned))? = State { closed: true, ..State::default() };
+        Ok(())
+    }
+}
+
+pub(crate) enum BatchOp {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+}
+
+/// A batch of writes that is only applied to its store on commit.
+pub struct MemoryBatch<'a> {
+    store: &'a MemoryStore,
+    ops: Vec<BatchOp>,
+}
+
+impl Batch for MemoryBatch<'_> {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.ops.push(BatchOp::Put(key.to_vec(), value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.ops.push(BatchOp::Delete(key.to_vec()));
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), Box<dyn Error>> {
+        self.store.apply(std::mem::take(&mut self.ops))?;
+        Ok(())
+    }
+}
+
+impl Iterable for MemoryStore {
+    fn get_iterator(&self, query: DataQuery) -> Result<Box<dyn iterable::Iterator>, Box<dyn Error>> {
+        drop(self.read()?);
+        Ok(Box::new(MemoryIterator::new(Rc::new(self.clone()), query)))
+    }
+
+    fn count_prefix(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<usize, Box<dyn Error>> {
+        Ok(count_range(&self.read()?.values, start_prefix, end_prefix))
+    }
+
+    fn iterate_keys_only(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        Ok(keys_in_range(&self.read()?.values, start_prefix, end_prefix))
+    }
+}
+
+impl IterableDatastore for MemoryStore {
+    fn delete_prefix(&self, prefix: &[u8]) -> Result<usize, Box<dyn Error>> {
+        Ok(self.write()?.remove_prefix(prefix))
+    }
+}
+
+impl IterableTxnDatastore for MemoryStore {
+    fn new_iterable_transaction(&self, read_only: bool) -> Result<Box<dyn IterableTxn + '_>, Box<dyn Error>> {
+        Ok(Box::new(MemoryTxn::new(self, read_only)?))
+    }
+
+    fn clone_box(&self) -> Box<dyn IterableTxnDatastore> {
+        Box::new(self.clone())
+    }
+}
+
+// The entries an iterator walks, the live store or the snapshot of a transaction.
+trait Entries {
+    // Returns the entry `skip` entries into the range in the given order, along with its version.
+    fn nth(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>, order: Order, skip: usize) -> Result<Option<Entry>, DatastoreError>;
+}
+
+type Entry = (Vec<u8>, Vec<u8>, Option<u64>);
+
+impl Entries for MemoryStore {
+    fn nth(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>, order: Order, skip: usize) -> Result<Option<Entry>, DatastoreError> {
+        let state = self.read()?;
+        Ok(nth_in_range(&state.values, &state.versions, (lower, upper), order, skip))
+    }
+}
+
+/// Walks the entries one at a time, every step only looks up the entry after the last one returned and nothing is
+/// buffered. Iterators over the store see the writes made while iterating.
+pub struct MemoryIterator {
+    entries: Rc<dyn Entries>,
+    query: DataQuery,
+    // `next_kv` walks the keys under the query prefix in `[start, end)`, an empty `end` walks to the end.
+    start: Vec<u8>,
+    end: Vec<u8>,
+    cursor: Option<Vec<u8>>,
+    position: Position,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Position {
+    // The key last returned and its version.
+    last: Option<Vec<u8>>,
+    version: Option<u64>,
+    returned: usize,
+}
+
+impl MemoryIterator {
+    fn new(entries: Rc<dyn Entries>, query: DataQuery) -> Self {
+        MemoryIterator {
+            entries,
+            query,
+            start: Vec::new(),
+            end: Vec::new(),
+            cursor: None,
+            position: Position::default(),
+            closed: false,
+        }
+    }
+
+    // A new walk over `[start, end)` with the same query and cursor.
+    fn walk(&self, start: &[u8], end: &[u8]) -> Self {
+        MemoryIterator {
+            start: start.to_vec(),
+            end: end.to_vec(),
+            cursor: self.cursor.clone(),
+            ..MemoryIterator::new(Rc::clone(&self.entries), self.query.clone())
+        }
+    }
+}
+
+impl iterable::Iterator for MemoryIterator {
+    fn iterate_prefix(&self, start_prefix: String, end_prefix: String) -> Result<Vec<Box<dyn StoreData>>, Box<dyn Error>> {
+        if self.closed {
+            return Err(DatastoreError::from(MemoryError::IteratorClosed).into());
+        }
+
+        let mut walk = self.walk(start_prefix.as_bytes(), end_prefix.as_bytes());
+        let mut results: Vec<Box<dyn StoreData>> = Vec::new();
+        while let Some((key, value)) = walk.next_kv()? {
+            results.push(Box::new(KeyValue::new(key, value).with_version(walk.position.version)));
+        }
+
+        Ok(results)
+    }
+
+    // Honours the cursor, order, offset and limit of the query.
+    fn next_kv(&mut self) -> Result<Option<KeyValuePair>, Box<dyn Error>> {
+        if self.closed {
+            return Err(DatastoreError::from(MemoryError::IteratorClosed).into());
+        }
+        if self.position.returned >= self.query.limit.unwrap_or(usize::MAX) {
+            return Ok(None);
+        }
+
+        let prefix = self.query.prefix.as_deref().unwrap_or_default();
+        let prefix_end = prefix_end(prefix);
+        let start = self.start.as_slice().max(prefix);
+        let mut lower = Bound::Included(start);
+        let mut upper = match (self.end.as_slice(), prefix_end.as_deref()) {
+            ([], None) => Bound::Unbounded,
+            ([], Some(end)) => Bound::Excluded(end),
+            (end, None) => Bound::Excluded(end),
+            (end, Some(prefix_end)) => Bound::Excluded(end.min(prefix_end)),
+        };
+
+        // The cursor narrows the range from the side the iteration starts at.
+        match (self.cursor.as_deref(), self.query.order) {
+            (Some(cursor), Order::Ascending) if cursor > start => lower = Bound::Included(cursor),
+            (Some(cursor), Order::Descending) => match upper {
+                Bound::Excluded(bound) if bound <= cursor => {}
+                _ => upper = Bound::Included(cursor),
+            },
+            _ => {}
+        }
+
+        let skip = match self.position.last.as_deref() {
+            Some(last) => {
+                match self.query.order {
+                    Order::Ascending => lower = Bound::Excluded(last),
+                    Order::Descending => upper = Bound::Excluded(last),
+                }
+                0
+            }
+            None => self.query.offset.unwrap_or(0),
+        };
+
+        let Some((key, value, version)) = self.entries.nth(lower, upper, self.query.order, skip)? else {
+            return Ok(None);
+        };
+        self.position = Position { last: Some(key.clone()), version, returned: self.position.returned + 1 };
+        Ok(Some((key, value)))
+    }
+
+    fn seek(&mut self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        if self.closed {
+            return Err(DatastoreError::from(MemoryError::IteratorClosed).into());
+        }
+
+        self.cursor = Some(key.to_vec());
+        self.position = Position::default();
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        self.closed = true;
+        Ok(())
+    }
+}
+
+fn read_u64(data: &mut &[u8]) -> Result<u64, DatastoreError> {
+    let bytes = read_bytes(data, 8)?;
+    Ok(u64::from_le_bytes(bytes.try_into().expect("read exactly 8 bytes")))
+}
+
+fn read_bytes(data: &mut &[u8], len: u64) -> Result<Vec<u8>, DatastoreError> {
+    let len = usize::try_from(len).ok().filter(|len| *len <= data.len());
+    let Some(len) = len else {
+        return Err(MemoryError::InvalidSnapshot("unexpected end of file".to_string()).into());
+    };
+    let (bytes, rest) = data.split_at(len);
+    *data = rest;
+    Ok(bytes.to_vec())
+}
+
+fn entry_size(key: &[u8], value: &[u8]) -> u64 {
+    (key.len() + value.len()) as u64
+}
+
+fn count_range(values: &KeyValues, start: &[u8], end: &[u8]) -> usize {
+    range(values, start, end).map_or(0, |entries| entries.count())
+}
+
+fn keys_in_range(values: &KeyValues, start: &[u8], end: &[u8]) -> Vec<Vec<u8>> {
+    range(values, start, end).map_or_else(Vec::new, |entries| entries.map(|(key, _)| key.clone()).collect())
+}
+
+// `BTreeMap::range` panics on a reversed range, so those are `None`.
+fn range<'a>(values: &'a KeyValues, start: &'a [u8], end: &'a [u8]) -> Option<btree_map::Range<'a, Vec<u8>, Vec<u8>>> {
+    let end = match end {
+        [] => Bound::Unbounded,
+        end => Bound::Excluded(end),
+    };
+    if is_empty_range(Bound::Included(start), end) {
+        return None;
+    }
+    Some(values.range::<[u8], _>((Bound::Included(start), end)))
+}
+
+fn nth_in_range(values: &KeyValues, versions: &Versions, range: (Bound<&[u8]>, Bound<&[u8]>), order: Order, skip: usize) -> Option<Entry> {
+    // BTreeMap::range panics on an inverted range, which is simply empty here.
+    if is_empty_range(range.0, range.1) {
+        return None;
+    }
+    let mut entries = values.range::<[u8], _>(range);
+    let (key, value) = match order {
+        Order::Ascending => entries.nth(skip),
+        Order::Descending => entries.nth_back(skip),
+    }?;
+    Some((key.clone(), value.clone(), versions.get(key).copied()))
+}
+
+// The first key past every key starting with `prefix`, `None` when there is no such key.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+fn is_empty_range(lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> bool {
+    match (lower, upper) {
+        (Bound::Unbounded, _) | (_, Bound::Unbounded) => false,
+        (Bound::Included(lower), Bound::Included(upper)) => upper < lower,
+        (Bound::Included(lower) | Bound::Excluded(lower), Bound::Excluded(upper))
+        | (Bound::Excluded(lower), Bound::Included(upper)) => upper <= lower,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastore::badger::v4::errors::BadgerError;
+
+    fn keys(entries: Vec<Box<dyn StoreData>>) -> Vec<String> {
+        entries.iter().map(|entry| String::from_utf8(entry.key().to_vec()).unwrap()).collect()
+    }
+
+    fn store_with(keys: &[&str]) -> MemoryStore {
+        let store = MemoryStore::new();
+        for key in keys {
+            store.put(key.as_bytes(), key.as_bytes()).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn put_get_delete_round_trip() {
+        let store = MemoryStore::new();
+        store.put(b"k", b"v").unwrap();
+        assert_eq!(store.get(b"k").unwrap(), Some(b"v".to_vec()));
+
+        store.put(b"k", b"w").unwrap();
+        assert_eq!(store.get(b"k").unwrap(), Some(b"w".to_vec()));
+
+        store.delete(b"k").unwrap();
+        assert_eq!(store.get(b"k").unwrap(), None);
+        store.delete(b"k").unwrap();
+    }
+
+    #[test]
+    fn prefix_scans_return_sorted_keys() {
+        let store = store_with(&["user/c", "post/a", "user/a", "user/b", "users"]);
+        let iter = store.get_iterator(DataQuery::new().prefix(b"user/".to_vec())).unwrap();
+
+        assert_eq!(keys(iter.iterate_prefix(String::new(), String::new()).unwrap()), ["user/a", "user/b", "user/c"]);
+    }
+
+    #[test]
+    fn iterate_prefix_is_a_half_open_range() {
+        let store = store_with(&["a", "ab", "abc", "b", "ba"]);
+        let iter = store.get_iterator(DataQuery::new()).unwrap();
+        let range = |start: &str, end: &str| keys(iter.iterate_prefix(start.to_string(), end.to_string()).unwrap());
+
+        assert_eq!(range("ab", ""), ["ab", "abc", "b", "ba"]);
+        assert_eq!(range("b", "b"), Vec::<String>::new());
+        assert_eq!(range("b", "a"), Vec::<String>::new());
+        assert_eq!(range("a", "abc"), ["a", "ab"]);
+        assert_eq!(range("ab", "b"), ["ab", "abc"]);
+    }
+
+    #[test]
+    fn descending_iteration_reverses_the_keys() {
+        let store = store_with(&["p/a", "p/b", "p/c", "q"]);
+        let scan = |order: Order| {
+            let iter = store.get_iterator(DataQuery::new().prefix(b"p/".to_vec()).order(order)).unwrap();
+            keys(iter.iterate_prefix(String::new(), String::new()).unwrap())
+        };
+
+        let mut ascending = scan(Order::Ascending);
+        ascending.reverse();
+        assert_eq!(scan(Order::Descending), ascending);
+        assert_eq!("desc".parse::<Order>().unwrap(), Order::Descending);
+        assert!(matches!("sideways".parse::<Order>(), Err(BadgerError::InvalidOrderType(_))));
+    }
+
+    #[test]
+    fn queries_honour_limit_and_offset() {
+        let store = store_with(&["k/1", "k/2", "k/3", "k/4", "k/5", "other"]);
+        let scan = |query: DataQuery| {
+            let iter = store.get_iterator(query.prefix(b"k/".to_vec())).unwrap();
+            keys(iter.iterate_prefix(String::new(), String::new()).unwrap())
+        };
+
+        assert_eq!(scan(DataQuery::new().limit(2)), ["k/1", "k/2"]);
+        assert_eq!(scan(DataQuery::new().offset(3)), ["k/4", "k/5"]);
+        assert_eq!(scan(DataQuery::new().offset(1).limit(2)), ["k/2", "k/3"]);
+        assert_eq!(scan(DataQuery::new().offset(1).limit(2).order(Order::Descending)), ["k/4", "k/3"]);
+        assert!(scan(DataQuery::new().offset(5)).is_empty());
+    }
+
+    #[test]
+    fn batches_are_only_visible_after_commit() {
+        let store = MemoryStore::new();
+        let mut batch = store.batch().unwrap();
+        for i in 0..100u8 {
+            batch.put(&[i], &[i]).unwrap();
+        }
+        assert_eq!(store.count_prefix(b"", b"").unwrap(), 0);
+
+        batch.commit().unwrap();
+        assert_eq!(store.count_prefix(b"", b"").unwrap(), 100);
+        assert_eq!(store.get(&[42]).unwrap(), Some(vec![42]));
+
+        let mut dropped = store.batch().unwrap();
+        dropped.put(b"dropped", b"v").unwrap();
+        dropped.delete(&[0]).unwrap();
+        drop(dropped);
+        assert!(!store.has(b"dropped").unwrap());
+        assert!(store.has(&[0]).unwrap());
+    }
+
+    #[test]
+    fn has_and_get_size_report_present_and_absent_keys() {
+        let store = MemoryStore::new();
+        store.put(b"k", b"value").unwrap();
+        store.put(b"empty", b"").unwrap();
+
+        assert!(store.has(b"k").unwrap());
+        assert_eq!(store.get_size(b"k").unwrap(), Some(5));
+        assert!(store.has(b"empty").unwrap());
+        assert_eq!(store.get_size(b"empty").unwrap(), Some(0));
+        assert!(!store.has(b"missing").unwrap());
+        assert_eq!(store.get_size(b"missing").unwrap(), None);
+    }
+
+    #[test]
+    fn keys_expire_after_their_ttl() {
+        let store = MemoryStore::new();
+        store.put_with_ttl(b"short", b"v", Duration::from_millis(10)).unwrap();
+        store.put_with_ttl(b"long", b"v", Duration::from_secs(60)).unwrap();
+        assert!(store.has(b"short").unwrap());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(store.get(b"short").unwrap(), None);
+        assert!(store.has(b"long").unwrap());
+        assert_eq!(store.count_prefix(b"", b"").unwrap(), 1);
+
+        // A plain put clears the expiry.
+        store.put_with_ttl(b"short", b"v", Duration::from_millis(10)).unwrap();
+        store.put(b"short", b"v").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(store.has(b"short").unwrap());
+    }
+
+    #[test]
+    fn delete_prefix_only_removes_the_prefixed_keys() {
+        let store = store_with(&["user/1", "user/2", "user/3", "users", "post/1", "a"]);
+
+        assert_eq!(store.delete_prefix(b"user/").unwrap(), 3);
+        let iter = store.get_iterator(DataQuery::new()).unwrap();
+        assert_eq!(keys(iter.iterate_prefix(String::new(), String::new()).unwrap()), ["a", "post/1", "users"]);
+        assert_eq!(store.delete_prefix(b"user/").unwrap(), 0);
+    }
+
+    #[test]
+    fn seek_resumes_at_the_cursor() {
+        let store = store_with(&["a1", "a2", "a3", "a4", "b1"]);
+        let mut iter = store.get_iterator(DataQuery::new().prefix(b"a".to_vec())).unwrap();
+        iter.seek(b"a3").unwrap();
+        assert_eq!(keys(iter.iterate_prefix(String::new(), String::new()).unwrap()), ["a3", "a4"]);
+        iter.seek(b"z").unwrap();
+        assert!(iter.iterate_prefix(String::new(), String::new()).unwrap().is_empty());
+
+        let mut iter = store.get_iterator(DataQuery::new().order(Order::Descending)).unwrap();
+        iter.seek(b"a3").unwrap();
+        assert_eq!(keys(iter.iterate_prefix(String::new(), String::new()).unwrap()), ["a3", "a2", "a1"]);
+        iter.seek(b"0").unwrap();
+        assert!(iter.iterate_prefix(String::new(), String::new()).unwrap().is_empty());
+
+        iter.close().unwrap();
+        assert!(iter.seek(b"a").is_err());
+    }
+
+    #[test]
+    fn next_kv_streams_the_prefix_in_order() {
+        let store = store_with(&["a1", "a2", "a3", "b1"]);
+        let mut iter = store.get_iterator(DataQuery::new().prefix(b"a".to_vec())).unwrap();
+
+        let mut streamed = Vec::new();
+        while let Some((key, value)) = iter.next_kv().unwrap() {
+            assert_eq!(key, value);
+            streamed.push(String::from_utf8(key).unwrap());
+        }
+        assert_eq!(streamed, ["a1", "a2", "a3"]);
+        assert_eq!(iter.next_kv().unwrap(), None);
+
+        iter.seek(b"a2").unwrap();
+        assert_eq!(iter.next_kv().unwrap(), Some((b"a2".to_vec(), b"a2".to_vec())));
+    }
+
+    #[test]
+    fn count_prefix_counts_the_keys_in_range() {
+        let store = store_with(&["a1", "a2", "a3", "b1"]);
+        assert_eq!(store.count_prefix(b"a", b"b").unwrap(), 3);
+        assert_eq!(store.count_prefix(b"a2", b"").unwrap(), 3);
+        assert_eq!(store.count_prefix(b"c", b"d").unwrap(), 0);
+        assert_eq!(store.count_prefix(b"b", b"a").unwrap(), 0);
+    }
+
+    #[test]
+    fn clones_share_the_same_data() {
+        let store = MemoryStore::new();
+        let clone = store.clone();
+        store.put(b"k", b"v").unwrap();
+        assert_eq!(clone.get(b"k").unwrap(), Some(b"v".to_vec()));
+
+        let writer = clone.clone();
+        std::thread::spawn(move || writer.put(b"t", b"x").unwrap()).join().unwrap();
+        assert!(store.has(b"t").unwrap());
+
+        let boxed = store.clone_box();
+        let other = boxed.clone();
+        boxed.put(b"boxed", b"v").unwrap();
+        assert!(other.has(b"boxed").unwrap());
+    }
+
+    #[test]
+    fn snapshots_restore_an_identical_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshot");
+        let store = MemoryStore::new();
+        for i in 0..100u32 {
+            store.put(&i.to_be_bytes(), &vec![i as u8; i as usize]).unwrap();
+        }
+        store.put(b"", b"").unwrap();
+        store.snapshot(&path).unwrap();
+
+        let restored = MemoryStore::restore(&path).unwrap();
+        let entries = |store: &MemoryStore| {
+            let iter = store.get_iterator(DataQuery::new()).unwrap();
+            iter.iterate_prefix(String::new(), String::new()).unwrap().into_iter().map(StoreData::into_pair).collect::<Vec<_>>()
+        };
+        assert_eq!(entries(&restored).len(), 101);
+        assert_eq!(entries(&restored), entries(&store));
+    }
+
+    #[test]
+    fn truncated_snapshots_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshot");
+        store_with(&["a", "b"]).snapshot(&path).unwrap();
+        let data = fs::read(&path).unwrap();
+
+        for len in [0, 5, 8, 20, data.len() - 1] {
+            fs::write(&path, &data[..len]).unwrap();
+            let err = MemoryStore::restore(&path).unwrap_err();
+            assert!(err.to_string().contains("invalid snapshot"), "{}: {}", len, err);
+        }
+        fs::write(&path, [data.as_slice(), &[0]].concat()).unwrap();
+        assert!(MemoryStore::restore(&path).is_err());
+    }
+
+    #[test]
+    fn put_if_absent_keeps_the_first_value() {
+        let store = MemoryStore::new();
+        assert!(store.put_if_absent(b"k", b"1").unwrap());
+        assert!(!store.put_if_absent(b"k", b"2").unwrap());
+        assert_eq!(store.get(b"k").unwrap(), Some(b"1".to_vec()));
+
+        let written: usize = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || store.put_if_absent(b"race", b"x").unwrap() as usize)
+            })
+            .map(|thread| thread.join().unwrap())
+            .sum();
+        assert_eq!(written, 1);
+    }
+
+    #[test]
+    fn cas_only_swaps_the_expected_value() {
+        let store = MemoryStore::new();
+        assert!(store.cas(b"k", None, b"1").unwrap());
+        assert!(!store.cas(b"k", None, b"2").unwrap());
+        assert!(!store.cas(b"k", Some(b"x"), b"2").unwrap());
+        assert_eq!(store.get(b"k").unwrap(), Some(b"1".to_vec()));
+
+        assert!(store.cas(b"k", Some(b"1"), b"2").unwrap());
+        assert_eq!(store.get(b"k").unwrap(), Some(b"2".to_vec()));
+        assert!(!store.cas(b"missing", Some(b"1"), b"2").unwrap());
+        assert!(!store.has(b"missing").unwrap());
+    }
+
+    #[test]
+    fn writes_past_the_capacity_fail_without_changes() {
+        let store = MemoryStore::with_capacity(20);
+        store.put(b"aaaa", b"123456").unwrap();
+        store.put(b"bbbb", b"123456").unwrap();
+
+        let err = store.put(b"c", b"").unwrap_err();
+        assert!(matches!(err.downcast_ref::<DatastoreError>(), Some(DatastoreError::OutOfSpace { capacity: 20 })), "{}", err);
+        assert!(!store.has(b"c").unwrap());
+
+        // Replacing a value only counts the difference in size.
+        store.put(b"aaaa", b"654321").unwrap();
+        assert!(store.put(b"aaaa", b"1234567").is_err());
+
+        store.delete(b"bbbb").unwrap();
+        store.put(b"cccc", b"123456").unwrap();
+        assert_eq!(store.get(b"aaaa").unwrap(), Some(b"654321".to_vec()));
+    }
+
+    #[test]
+    fn rewriting_a_key_bumps_its_version() {
+        let store = MemoryStore::new();
+        let version = |key: &str| {
+            let entries = store.get_iterator(DataQuery::new()).unwrap().iterate_prefix(String::new(), String::new()).unwrap();
+            entries.into_iter().find(|e| e.key() == key.as_bytes()).and_then(|e| e.version())
+        };
+
+        store.put(b"a", b"1").unwrap();
+        let first = version("a").unwrap();
+        store.put(b"b", b"1").unwrap();
+        store.put(b"a", b"2").unwrap();
+        let second = version("a").unwrap();
+        assert!(second > first);
+        assert!(second > version("b").unwrap());
+
+        store.delete(b"a").unwrap();
+        store.put(b"a", b"1").unwrap();
+        assert!(version("a").unwrap() > second);
+    }
+
+    #[test]
+    fn next_kv_reads_the_store_one_step_at_a_time() {
+        let store = store_with(&["a1", "a3"]);
+        let mut iter = store.get_iterator(DataQuery::new().prefix(b"a")).unwrap();
+        assert_eq!(iter.next_kv().unwrap().map(|(key, _)| key), Some(b"a1".to_vec()));
+
+        // Nothing is buffered, so a key written after the iterator was created is still returned.
+        store.put(b"a2", b"v").unwrap();
+        assert_eq!(iter.next_kv().unwrap().map(|(key, _)| key), Some(b"a2".to_vec()));
+        assert_eq!(iter.next_kv().unwrap().map(|(key, _)| key), Some(b"a3".to_vec()));
+        assert_eq!(iter.next_kv().unwrap(), None);
+    }
+
+    #[test]
+    fn descending_prefix_scans_stop_at_the_prefix() {
+        let store = MemoryStore::new();
+        for key in [&b"a\xff"[..], b"a\xff\x01", b"b", b"\xff\xff"] {
+            store.put(key, b"v").unwrap();
+        }
+        let query = |prefix: &[u8]| DataQuery { order: Order::Descending, ..DataQuery::new().prefix(prefix) };
+
+        let entries = store.get_iterator(query(b"a\xff")).unwrap().iterate_prefix(String::new(), String::new()).unwrap();
+        let keys: Vec<_> = entries.iter().map(|entry| entry.key().to_vec()).collect();
+        assert_eq!(keys, vec![b"a\xff\x01".to_vec(), b"a\xff".to_vec()]);
+
+        let mut iter = store.get_iterator(query(b"\xff")).unwrap();
+        assert_eq!(iter.next_kv().unwrap().map(|(key, _)| key), Some(b"\xff\xff".to_vec()));
+        assert_eq!(iter.next_kv().unwrap(), None);
+    }
+}
@@ -0,0 +1,247 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::rc::Rc;
+
+use crate::datastore::{Read, Write, Txn, DataQuery, DatastoreError, Error, Order};
+use crate::datastore::iterable::{self, Iterable, IterableTxn};
+
+use super::{keys_in_range, nth_in_range, BatchOp, Entries, Entry, KeyValues, MemoryError, MemoryIterator, MemoryStore, Versions};
+
+/// A transaction over a snapshot of a `MemoryStore` taken when the transaction is opened.
+/// Writes are visible within the transaction and only reach the store on commit, until then they have no version.
+/// Committing fails with `DatastoreError::Conflict` if any key the transaction read or wrote was changed in the store
+/// after the snapshot was taken, in which case none of the writes are applied.
+pub struct MemoryTxn<'a> {
+    store: &'a MemoryStore,
+    // Shared with the iterators of the transaction.
+    snapshot: Rc<RefCell<Snapshot>>,
+    ops: RefCell<Vec<BatchOp>>,
+    read_only: bool,
+    finished: bool,
+}
+
+#[derive(Default)]
+struct Snapshot {
+    values: KeyValues,
+    versions: Versions,
+    // The snapshot version of every key read or written, recorded the first time the key is touched.
+    touched: HashMap<Vec<u8>, Option<u64>>,
+}
+
+impl Snapshot {
+    fn touch(&mut self, key: &[u8]) {
+        if !self.touched.contains_key(key) {
+            let version = self.versions.get(key).copied();
+            self.touched.insert(key.to_vec(), version);
+        }
+    }
+}
+
+// Only the keys an iterator returns count as read.
+impl Entries for RefCell<Snapshot> {
+    fn nth(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>, order: Order, skip: usize) -> Result<Option<Entry>, DatastoreError> {
+        let mut snapshot = self.borrow_mut();
+        let entry = nth_in_range(&snapshot.values, &snapshot.versions, (lower, upper), order, skip);
+        if let Some((key, _, _)) = &entry {
+            snapshot.touch(key);
+        }
+        Ok(entry)
+    }
+}
+
+impl<'a> MemoryTxn<'a> {
+    pub(super) fn new(store: &'a MemoryStore, read_only: bool) -> Result<Self, DatastoreError> {
+        let state = store.read()?;
+        let snapshot = Snapshot { values: state.values.clone(), versions: state.versions.clone(), ..Snapshot::default() };
+        Ok(MemoryTxn {
+            store,
+            snapshot: Rc::new(RefCell::new(snapshot)),
+            ops: RefCell::new(Vec::new()),
+            read_only,
+            finished: false,
+        })
+    }
+
+    fn ensure_writable(&self) -> Result<(), DatastoreError> {
+        if self.finished {
+            return Err(MemoryError::TxnFinished.into());
+        }
+        if self.read_only {
+            return Err(DatastoreError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    // Returns the snapshot with `key` recorded as touched.
+    fn touch(&self, key: &[u8]) -> std::cell::RefMut<'_, Snapshot> {
+        let mut snapshot = self.snapshot.borrow_mut();
+        snapshot.touch(key);
+        snapshot
+    }
+
+    fn keys_in_range(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Vec<Vec<u8>> {
+        let mut snapshot = self.snapshot.borrow_mut();
+        let keys = keys_in_range(&snapshot.values, start_prefix, end_prefix);
+        keys.iter().for_each(|key| snapshot.touch(key));
+        keys
+    }
+}
+
+impl Read for MemoryTxn<'_> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        Ok(self.touch(key).values.get(key).cloned())
+    }
+
+    fn has(&self, key: &[u8]) -> Result<bool, Box<dyn Error>> {
+        Ok(self.touch(key).values.contains_key(key))
+    }
+
+    fn get_size(&self, key: &[u8]) -> Result<Option<usize>, Box<dyn Error>> {
+        Ok(self.touch(key).values.get(key).map(Vec::len))
+    }
+}
+
+impl Write for MemoryTxn<'_> {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.ensure_writable()?;
+        let mut snapshot = self.touch(key);
+        snapshot.values.insert(key.to_vec(), value.to_vec());
+        snapshot.versions.remove(key);
+        self.ops.borrow_mut().push(BatchOp::Put(key.to_vec(), value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.ensure_writable()?;
+        let mut snapshot = self.touch(key);
+        snapshot.values.remove(key);
+        snapshot.versions.remove(key);
+        self.ops.borrow_mut().push(BatchOp::Delete(key.to_vec()));
+        Ok(())
+    }
+
+    fn cas(&self, key: &[u8], expected: Option<&[u8]>, new: &[u8]) -> Result<bool, Box<dyn Error>> {
+        self.ensure_writable()?;
+        if self.touch(key).values.get(key).map(Vec::as_slice) != expected {
+            return Ok(false);
+        }
+        self.put(key, new)?;
+        Ok(true)
+    }
+}
+
+impl Txn for MemoryTxn<'_> {
+    fn commit(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.finished {
+            return Err(DatastoreError::from(MemoryError::TxnFinished).into());
+        }
+        self.finished = true;
+
+        // Without writes there is nothing to apply, the transaction only ever saw its own snapshot.
+        let ops = self.ops.take();
+        if ops.is_empty() {
+            return Ok(());
+        }
+        self.store.apply_if_unchanged(&self.snapshot.borrow().touched, ops)?;
+        Ok(())
+    }
+
+    fn discard(&mut self) {
+        self.finished = true;
+        self.ops.take();
+        self.snapshot.borrow_mut().touched.clear();
+    }
+}
+
+impl Iterable for MemoryTxn<'_> {
+    fn get_iterator(&self, query: DataQuery) -> Result<Box<dyn iterable::Iterator>, Box<dyn Error>> {
+        // Keys added to the range by other transactions aren't detected, only changes to the keys seen here.
+        Ok(Box::new(MemoryIterator::new(self.snapshot.clone(), query)))
+    }
+
+    fn count_prefix(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<usize, Box<dyn Error>> {
+        Ok(self.keys_in_range(start_prefix, end_prefix).len())
+    }
+
+    fn iterate_keys_only(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        Ok(self.keys_in_range(start_prefix, end_prefix))
+    }
+}
+
+impl IterableTxn for MemoryTxn<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_makes_writes_visible() {
+        let store = MemoryStore::new();
+        let mut txn = MemoryTxn::new(&store, false).unwrap();
+        txn.put(b"a", b"1").unwrap();
+        assert_eq!(txn.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(store.get(b"a").unwrap(), None);
+
+        txn.commit().unwrap();
+        assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn discard_drops_writes() {
+        let store = MemoryStore::new();
+        let mut txn = MemoryTxn::new(&store, false).unwrap();
+        txn.put(b"a", b"1").unwrap();
+        txn.discard();
+
+        assert_eq!(store.get(b"a").unwrap(), None);
+        assert!(txn.put(b"a", b"1").is_err());
+    }
+
+    #[test]
+    fn read_only_txns_reject_writes() {
+        let store = MemoryStore::new();
+        let txn = MemoryTxn::new(&store, true).unwrap();
+        let err = txn.put(b"a", b"1").unwrap_err();
+        assert!(matches!(err.downcast_ref::<DatastoreError>(), Some(DatastoreError::ReadOnly)));
+    }
+
+    #[test]
+    fn concurrent_cas_on_the_same_key_conflicts() {
+        let store = MemoryStore::new();
+        store.put(b"counter", b"0").unwrap();
+        let mut first = MemoryTxn::new(&store, false).unwrap();
+        let mut second = MemoryTxn::new(&store, false).unwrap();
+        assert!(first.cas(b"counter", Some(b"0"), b"1").unwrap());
+        assert!(second.cas(b"counter", Some(b"0"), b"2").unwrap());
+
+        first.commit().unwrap();
+        let err = second.commit().unwrap_err();
+        assert!(matches!(err.downcast_ref::<DatastoreError>(), Some(DatastoreError::Conflict)));
+        assert_eq!(store.get(b"counter").unwrap(), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn a_read_key_changed_in_the_store_conflicts() {
+        let store = MemoryStore::new();
+        let mut txn = MemoryTxn::new(&store, false).unwrap();
+        assert_eq!(txn.get(b"a").unwrap(), None);
+        txn.put(b"b", b"1").unwrap();
+        store.put(b"a", b"1").unwrap();
+
+        let err = txn.commit().unwrap_err();
+        assert!(matches!(err.downcast_ref::<DatastoreError>(), Some(DatastoreError::Conflict)));
+        assert_eq!(store.get(b"b").unwrap(), None);
+    }
+
+    #[test]
+    fn changes_to_untouched_keys_do_not_conflict() {
+        let store = MemoryStore::new();
+        let mut txn = MemoryTxn::new(&store, false).unwrap();
+        txn.put(b"a", b"1").unwrap();
+        store.put(b"b", b"1").unwrap();
+
+        txn.commit().unwrap();
+        assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+    }
+}
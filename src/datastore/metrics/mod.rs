@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::datastore::{Store, Read, Write, Batch, StoreData, DataQuery, Error, KeyValuePair};
+use crate::datastore::iterable::{self, Iterable};
+
+/// The kind of operation a duration is recorded for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpKind {
+    Get,
+    Has,
+    Put,
+    Delete,
+    Cas,
+    Iterate,
+}
+
+/// A sink for the operations timed by `MeteredStore`.
+pub trait DatastoreMetrics: Send + Sync {
+    /// Sinks that drop everything set this to false, the operations are then not even timed.
+    const ENABLED: bool = true;
+
+    fn record_op(&self, op: OpKind, duration: Duration);
+}
+
+/// Discards every operation.
+impl DatastoreMetrics for () {
+    const ENABLED: bool = false;
+
+    fn record_op(&self, _op: OpKind, _duration: Duration) {}
+}
+
+/// Wraps a store and records the duration of every read, write and iteration in `metrics`.
+pub struct MeteredStore<S, M> {
+    inner: S,
+    metrics: Arc<M>,
+}
+
+impl<S, M: DatastoreMetrics> MeteredStore<S, M> {
+    pub fn new(inner: S, metrics: Arc<M>) -> Self {
+        MeteredStore { inner, metrics }
+    }
+
+    pub fn metrics(&self) -> &M {
+        &self.metrics
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+fn timed<M: DatastoreMetrics, T>(metrics: &M, op: OpKind, f: impl FnOnce() -> T) -> T {
+    if !M::ENABLED {
+        return f();
+    }
+
+    let start = Instant::now();
+    let result = f();
+    metrics.record_op(op, start.elapsed());
+    result
+}
+
+impl<S: Read, M: DatastoreMetrics> Read for MeteredStore<S, M> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Get, || self.inner.get(key))
+    }
+
+    fn has(&self, key: &[u8]) -> Result<bool, Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Has, || self.inner.has(key))
+    }
+
+    fn get_size(&self, key: &[u8]) -> Result<Option<usize>, Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Get, || self.inner.get_size(key))
+    }
+}
+
+impl<S: Write, M: DatastoreMetrics> Write for MeteredStore<S, M> {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Put, || self.inner.put(key, value))
+    }
+
+    fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> Result<(), Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Put, || self.inner.put_with_ttl(key, value, ttl))
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Delete, || self.inner.delete(key))
+    }
+
+    fn put_if_absent(&self, key: &[u8], value: &[u8]) -> Result<bool, Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Cas, || self.inner.put_if_absent(key, value))
+    }
+
+    fn cas(&self, key: &[u8], expected: Option<&[u8]>, new: &[u8]) -> Result<bool, Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Cas, || self.inner.cas(key, expected, new))
+    }
+}
+
+impl<S: Store, M: DatastoreMetrics> Store for MeteredStore<S, M> {
+    fn batch(&self) -> Result<Box<dyn Batch + '_>, Box<dyn Error>> {
+        self.inner.batch()
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        self.inner.close()
+    }
+}
+
+impl<S: Iterable, M: DatastoreMetrics + 'static> Iterable for MeteredStore<S, M> {
+    fn get_iterator(&self, query: DataQuery) -> Result<Box<dyn iterable::Iterator>, Box<dyn Error>> {
+        let inner = timed(&*self.metrics, OpKind::Iterate, || self.inner.get_iterator(query))?;
+        Ok(Box::new(MeteredIterator { inner, metrics: self.metrics.clone() }))
+    }
+
+    fn count_prefix(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<usize, Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Iterate, || self.inner.count_prefix(start_prefix, end_prefix))
+    }
+
+    fn iterate_keys_only(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Iterate, || self.inner.iterate_keys_only(start_prefix, end_prefix))
+    }
+}
+
+/// Records every step of the wrapped iterator as an `Iterate` operation.
+pub struct MeteredIterator<M> {
+    inner: Box<dyn iterable::Iterator>,
+    metrics: Arc<M>,
+}
+
+impl<M: DatastoreMetrics> iterable::Iterator for MeteredIterator<M> {
+    fn iterate_prefix(&self, start_prefix: String, end_prefix: String) -> Result<Vec<Box<dyn StoreData>>, Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Iterate, || self.inner.iterate_prefix(start_prefix, end_prefix))
+    }
+
+    fn seek(&mut self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.inner.seek(key)
+    }
+
+    fn next_kv(&mut self) -> Result<Option<KeyValuePair>, Box<dyn Error>> {
+        timed(&*self.metrics, OpKind::Iterate, || self.inner.next_kv())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        self.inner.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::datastore::memory::MemoryStore;
+
+    #[derive(Default)]
+    struct CountingMetrics(Mutex<HashMap<OpKind, usize>>);
+
+    impl DatastoreMetrics for CountingMetrics {
+        fn record_op(&self, op: OpKind, _duration: Duration) {
+            *self.0.lock().unwrap().entry(op).or_default() += 1;
+        }
+    }
+
+    #[test]
+    fn operations_are_counted_by_kind() {
+        let store = MeteredStore::new(MemoryStore::new(), Arc::new(CountingMetrics::default()));
+        store.put(b"a", b"1").unwrap();
+        store.put(b"b", b"2").unwrap();
+        store.get(b"a").unwrap();
+        store.delete(b"b").unwrap();
+        assert!(store.put_if_absent(b"c", b"3").unwrap());
+        let mut iter = store.get_iterator(DataQuery::new()).unwrap();
+        while iter.next_kv().unwrap().is_some() {}
+
+        let counts = store.metrics().0.lock().unwrap().clone();
+        assert_eq!(counts[&OpKind::Put], 2);
+        assert_eq!(counts[&OpKind::Get], 1);
+        assert_eq!(counts[&OpKind::Delete], 1);
+        assert_eq!(counts[&OpKind::Cas], 1);
+        // Opening the iterator, one step per entry and one for the end of the iteration.
+        assert_eq!(counts[&OpKind::Iterate], 4);
+    }
+
+    #[test]
+    fn the_unit_sink_passes_operations_through() {
+        let store = MeteredStore::new(MemoryStore::new(), Arc::new(()));
+        store.put(b"a", b"1").unwrap();
+        assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+    }
+}
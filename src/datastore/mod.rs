@@ -1,3 +1,355 @@
 pub mod badger;
-mod iterable;
-mod mocks;
\ No newline at end of file
+pub mod errors;
+pub mod iterable;
+pub mod memory;
+pub mod metrics;
+mod mocks;
+pub mod prefix;
+
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use log::error;
+
+use crate::datastore::badger::v4::errors::BadgerError;
+use crate::datastore::iterable::{IterableTxn, IterableTxnDatastore};
+
+pub use std::error::Error;
+pub use errors::DatastoreError;
+
+const TXN_RETRY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// A single key/value entry returned from a datastore.
+pub trait StoreData: std::fmt::Debug {
+    fn key(&self) -> &[u8];
+    fn value(&self) -> &[u8];
+    /// Returns the version of the write that stored the entry, later writes to a key have higher versions.
+    /// `None` when the store doesn't track versions or the write isn't committed yet.
+    fn version(&self) -> Option<u64> {
+        None
+    }
+    /// Consumes the entry, returning its key and value without copying them.
+    fn into_pair(self: Box<Self>) -> KeyValuePair;
+}
+
+/// An owned key and value, as streamed by `Iterator::next_kv`.
+pub type KeyValuePair = (Vec<u8>, Vec<u8>);
+
+/// The `StoreData` returned by the datastores.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyValue {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub version: Option<u64>,
+}
+
+impl KeyValue {
+    pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+        KeyValue { key, value, version: None }
+    }
+
+    pub fn with_version(mut self, version: Option<u64>) -> Self {
+        self.version = version;
+        self
+    }
+}
+
+impl StoreData for KeyValue {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    fn version(&self) -> Option<u64> {
+        self.version
+    }
+
+    fn into_pair(self: Box<Self>) -> KeyValuePair {
+        (self.key, self.value)
+    }
+}
+
+/// The key order in which an iterator returns its entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Order {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+impl FromStr for Order {
+    type Err = BadgerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "asc" | "ascending" => Ok(Order::Ascending),
+            "desc" | "descending" => Ok(Order::Descending),
+            _ => Err(BadgerError::InvalidOrderType(s.to_string())),
+        }
+    }
+}
+
+/// Parameters for creating an iterator over a datastore.
+#[derive(Debug, Clone, Default)]
+pub struct DataQuery {
+    pub prefix: Option<Vec<u8>>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub order: Order,
+}
+
+impl DataQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+}
+
+pub trait Read {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>>;
+
+    fn has(&self, key: &[u8]) -> Result<bool, Box<dyn Error>> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// Returns the size in bytes of the value stored at `key`, if any.
+    fn get_size(&self, key: &[u8]) -> Result<Option<usize>, Box<dyn Error>> {
+        Ok(self.get(key)?.map(|value| value.len()))
+    }
+}
+
+pub trait Write {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Box<dyn Error>>;
+
+    /// Writes a value that expires after `ttl`. Stores without expiry support keep the value permanently.
+    fn put_with_ttl(&self, key: &[u8], value: &[u8], _ttl: Duration) -> Result<(), Box<dyn Error>> {
+        self.put(key, value)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<(), Box<dyn Error>>;
+
+    /// Writes the value only if `key` doesn't exist yet, atomically. Returns whether the value was written.
+    fn put_if_absent(&self, key: &[u8], value: &[u8]) -> Result<bool, Box<dyn Error>> {
+        self.cas(key, None, value)
+    }
+
+    /// Replaces the value of `key` with `new` only if it currently is `expected`, atomically. An `expected` of `None`
+    /// requires the key to be absent. Returns whether the value was written.
+    fn cas(&self, key: &[u8], expected: Option<&[u8]>, new: &[u8]) -> Result<bool, Box<dyn Error>>;
+}
+
+/// Accumulates writes that are applied atomically on commit.
+pub trait Batch {
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Box<dyn Error>>;
+    fn delete(&mut self, key: &[u8]) -> Result<(), Box<dyn Error>>;
+    fn commit(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+/// A set of reads and writes that is applied on commit, or dropped on discard.
+pub trait Txn: Read + Write {
+    fn commit(&mut self) -> Result<(), Box<dyn Error>>;
+    fn discard(&mut self);
+}
+
+pub trait Store: Read + Write {
+    fn batch(&self) -> Result<Box<dyn Batch + '_>, Box<dyn Error>>;
+
+    /// Flushes and releases the store. Closing an already closed store is a no-op.
+    fn close(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+/// Closes the wrapped store when dropped, logging any error.
+pub struct StoreGuard<S: Store> {
+    store: S,
+}
+
+impl<S: Store> StoreGuard<S> {
+    pub fn new(store: S) -> Self {
+        StoreGuard { store }
+    }
+}
+
+impl<S: Store> Deref for StoreGuard<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.store
+    }
+}
+
+impl<S: Store> DerefMut for StoreGuard<S> {
+    fn deref_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+}
+
+impl<S: Store> Drop for StoreGuard<S> {
+    fn drop(&mut self) {
+        if let Err(err) = self.store.close() {
+            error!("failed to close datastore: {}", err);
+        }
+    }
+}
+
+/// Runs `f` in a new transaction and commits it, retrying up to `max_retries` times if either
+/// `f` or the commit fails with `DatastoreError::Conflict`. Backend errors like `BadgerError::TxnConflict` are only
+/// retried once converted into a `DatastoreError`.
+pub fn run_in_txn<S, T, F>(store: &S, max_retries: i32, mut f: F) -> Result<T, Box<dyn Error>>
+where
+    S: IterableTxnDatastore + ?Sized,
+    F: FnMut(&dyn IterableTxn) -> Result<T, Box<dyn Error>>,
+{
+    let mut attempt = 0;
+    loop {
+        let mut txn = store.new_iterable_transaction(false)?;
+        let result = f(&*txn).and_then(|value| txn.commit().map(|_| value));
+        match result {
+            Ok(value) => return Ok(value),
+            Err(err) if is_conflict(err.as_ref()) && attempt < max_retries => {
+                txn.discard();
+                attempt += 1;
+                thread::sleep(TXN_RETRY_BACKOFF * attempt as u32);
+            }
+            Err(err) => {
+                txn.discard();
+                return Err(err);
+            }
+        }
+    }
+}
+
+fn is_conflict(err: &(dyn Error + 'static)) -> bool {
+    matches!(err.downcast_ref::<DatastoreError>(), Some(DatastoreError::Conflict))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastore::memory::MemoryStore;
+
+    // Increments the counter, changing it behind the transaction's back on the first `conflicts` attempts.
+    fn increment(store: &MemoryStore, max_retries: i32, conflicts: i32) -> (Result<u8, Box<dyn Error>>, i32) {
+        let mut attempts = 0;
+        let result = run_in_txn(store, max_retries, |txn| {
+            attempts += 1;
+            let counter = txn.get(b"counter")?.map_or(0, |value| value[0]);
+            if attempts <= conflicts {
+                store.put(b"counter", &[counter + 10])?;
+            }
+            txn.put(b"counter", &[counter + 1])?;
+            Ok(counter + 1)
+        });
+        (result, attempts)
+    }
+
+    #[test]
+    fn run_in_txn_retries_a_conflict() {
+        let store = MemoryStore::new();
+        let (result, attempts) = increment(&store, 3, 1);
+
+        assert_eq!(result.unwrap(), 11);
+        assert_eq!(attempts, 2);
+        assert_eq!(store.get(b"counter").unwrap(), Some(vec![11]));
+    }
+
+    #[test]
+    fn run_in_txn_gives_up_after_max_retries() {
+        let store = MemoryStore::new();
+        let (result, attempts) = increment(&store, 2, i32::MAX);
+
+        assert!(is_conflict(result.unwrap_err().as_ref()));
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn run_in_txn_does_not_retry_other_errors() {
+        let store = MemoryStore::new();
+        let mut attempts = 0;
+        let result: Result<(), _> = run_in_txn(&store, 3, |_| {
+            attempts += 1;
+            Err(DatastoreError::NotFound(b"a".to_vec()).into())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn run_in_txn_retries_converted_badger_conflicts() {
+        let store = MemoryStore::new();
+        let mut attempts = 0;
+        let result = run_in_txn(&store, 3, |_| {
+            attempts += 1;
+            if attempts == 1 {
+                return Err(DatastoreError::from(BadgerError::TxnConflict).into());
+            }
+            Ok(attempts)
+        });
+
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[test]
+    fn closing_twice_is_a_no_op() {
+        let mut store = MemoryStore::new();
+        store.close().unwrap();
+        store.close().unwrap();
+        assert!(store.is_closed());
+    }
+
+    #[test]
+    fn store_guard_closes_on_drop() {
+        let store = MemoryStore::new();
+        {
+            let guard = StoreGuard::new(store.clone());
+            guard.put(b"k", b"v").unwrap();
+        }
+        assert!(store.is_closed());
+        let err = store.get(b"k").unwrap_err();
+        assert!(matches!(err.downcast_ref::<DatastoreError>(), Some(DatastoreError::Closed)));
+    }
+
+    #[test]
+    fn key_value_exposes_its_parts() {
+        let kv = KeyValue::new(b"k".to_vec(), b"v".to_vec());
+        assert_eq!(kv.key(), b"k");
+        assert_eq!(kv.value(), b"v");
+        assert_eq!(kv.version(), None);
+        assert_eq!(kv.clone().with_version(Some(3)).version(), Some(3));
+
+        let data: Box<dyn StoreData> = Box::new(kv);
+        assert_eq!(data.into_pair(), (b"k".to_vec(), b"v".to_vec()));
+    }
+
+    #[test]
+    fn datastore_errors_format_and_convert() {
+        assert_eq!(DatastoreError::NotFound(b"k".to_vec()).to_string(), "key not found: [107]");
+
+        let err: DatastoreError = std::io::Error::other("disk on fire").into();
+        assert!(matches!(err, DatastoreError::Io(_)));
+        assert_eq!(err.source().unwrap().to_string(), "disk on fire");
+    }
+}
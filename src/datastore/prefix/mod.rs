@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use crate::datastore::{Read, Write, StoreData, DataQuery, Error, KeyValue, KeyValuePair};
+use crate::datastore::iterable::{self, Iterable};
+
+/// Wraps a store so that every key lives under `prefix`. The prefix is prepended to the keys passed in and
+/// stripped from the keys returned, so users of the wrapper only see the keys within their namespace.
+pub struct PrefixStore<S> {
+    inner: S,
+    prefix: Vec<u8>,
+}
+
+impl<S> PrefixStore<S> {
+    pub fn new(inner: S, prefix: impl Into<Vec<u8>>) -> Self {
+        PrefixStore { inner, prefix: prefix.into() }
+    }
+
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn prefixed(&self, key: &[u8]) -> Vec<u8> {
+        [self.prefix.as_slice(), key].concat()
+    }
+
+    // An empty end is the end of the namespace rather than of the whole keyspace.
+    fn range_end(&self, end: &[u8]) -> Vec<u8> {
+        match end {
+            [] => prefix_end(&self.prefix),
+            end => self.prefixed(end),
+        }
+    }
+}
+
+impl<S: Read> Read for PrefixStore<S> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        self.inner.get(&self.prefixed(key))
+    }
+
+    fn has(&self, key: &[u8]) -> Result<bool, Box<dyn Error>> {
+        self.inner.has(&self.prefixed(key))
+    }
+
+    fn get_size(&self, key: &[u8]) -> Result<Option<usize>, Box<dyn Error>> {
+        self.inner.get_size(&self.prefixed(key))
+    }
+}
+
+impl<S: Write> Write for PrefixStore<S> {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.inner.put(&self.prefixed(key), value)
+    }
+
+    fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> Result<(), Box<dyn Error>> {
+        self.inner.put_with_ttl(&self.prefixed(key), value, ttl)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.inner.delete(&self.prefixed(key))
+    }
+
+    fn put_if_absent(&self, key: &[u8], value: &[u8]) -> Result<bool, Box<dyn Error>> {
+        self.inner.put_if_absent(&self.prefixed(key), value)
+    }
+
+    fn cas(&self, key: &[u8], expected: Option<&[u8]>, new: &[u8]) -> Result<bool, Box<dyn Error>> {
+        self.inner.cas(&self.prefixed(key), expected, new)
+    }
+}
+
+impl<S: Iterable> Iterable for PrefixStore<S> {
+    fn get_iterator(&self, mut query: DataQuery) -> Result<Box<dyn iterable::Iterator>, Box<dyn Error>> {
+        query.prefix = Some(self.prefixed(query.prefix.as_deref().unwrap_or_default()));
+        let inner = self.inner.get_iterator(query)?;
+        Ok(Box::new(PrefixIterator { inner, prefix: self.prefix.clone() }))
+    }
+
+    fn count_prefix(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<usize, Box<dyn Error>> {
+        self.inner.count_prefix(&self.prefixed(start_prefix), &self.range_end(end_prefix))
+    }
+
+    fn iterate_keys_only(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        let keys = self.inner.iterate_keys_only(&self.prefixed(start_prefix), &self.range_end(end_prefix))?;
+        Ok(keys.into_iter().map(|key| key[self.prefix.len()..].to_vec()).collect())
+    }
+}
+
+/// Iterates the inner store within the namespace, returning keys without the prefix.
+pub struct PrefixIterator {
+    inner: Box<dyn iterable::Iterator>,
+    prefix: Vec<u8>,
+}
+
+impl PrefixIterator {
+    // The range bounds of the iterator are strings, so a prefix that isn't UTF-8 is converted lossily.
+    fn prefixed(&self, key: String) -> String {
+        String::from_utf8_lossy(&self.prefix).into_owned() + &key
+    }
+
+    fn strip(&self, (key, value): KeyValuePair) -> KeyValuePair {
+        let key = key.strip_prefix(self.prefix.as_slice()).map(<[u8]>::to_vec).unwrap_or(key);
+        (key, value)
+    }
+}
+
+impl iterable::Iterator for PrefixIterator {
+    fn iterate_prefix(&self, start_prefix: String, end_prefix: String) -> Result<Vec<Box<dyn StoreData>>, Box<dyn Error>> {
+        // The query of the inner iterator is limited to the prefix, so an empty end stays unbounded.
+        let end_prefix = if end_prefix.is_empty() { end_prefix } else { self.prefixed(end_prefix) };
+        let entries = self.inner.iterate_prefix(self.prefixed(start_prefix), end_prefix)?;
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| {
+                let version = entry.version();
+                let (key, value) = self.strip(entry.into_pair());
+                Box::new(KeyValue::new(key, value).with_version(version)) as Box<dyn StoreData>
+            })
+            .collect())
+    }
+
+    fn seek(&mut self, key: &[u8]) -> Result<(), Box<dyn Error>> {
+        let key = [self.prefix.as_slice(), key].concat();
+        self.inner.seek(&key)
+    }
+
+    fn next_kv(&mut self) -> Result<Option<KeyValuePair>, Box<dyn Error>> {
+        Ok(self.inner.next_kv()?.map(|pair| self.strip(pair)))
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        self.inner.close()
+    }
+}
+
+// Returns the first key after every key starting with `prefix`, or an empty (unbounded) end if there is none.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::datastore::memory::MemoryStore;
+
+    fn keys(entries: Vec<Box<dyn StoreData>>) -> Vec<String> {
+        entries.iter().map(|entry| String::from_utf8(entry.key().to_vec()).unwrap()).collect()
+    }
+
+    #[test]
+    fn keys_are_namespaced_in_the_inner_store() {
+        let store = MemoryStore::new();
+        store.put(b"other", b"o").unwrap();
+        store.put(b"user0", b"o").unwrap();
+        let users = PrefixStore::new(store.clone(), "user/");
+        users.put(b"a", b"1").unwrap();
+        users.put(b"b", b"2").unwrap();
+
+        assert_eq!(store.get(b"user/a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(users.get(b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(users.get(b"other").unwrap(), None);
+
+        let mut iter = users.get_iterator(DataQuery::new()).unwrap();
+        assert_eq!(keys(iter.iterate_prefix(String::new(), String::new()).unwrap()), ["a", "b"]);
+        iter.seek(b"b").unwrap();
+        assert_eq!(iter.next_kv().unwrap(), Some((b"b".to_vec(), b"2".to_vec())));
+        assert_eq!(users.count_prefix(b"", b"").unwrap(), 2);
+
+        users.delete(b"a").unwrap();
+        assert!(!store.has(b"user/a").unwrap());
+    }
+
+    // Counts the iterators opened on the wrapped store, only those read values.
+    struct CountingStore {
+        inner: MemoryStore,
+        iterators: Cell<usize>,
+    }
+
+    impl Iterable for CountingStore {
+        fn get_iterator(&self, query: DataQuery) -> Result<Box<dyn iterable::Iterator>, Box<dyn Error>> {
+            self.iterators.set(self.iterators.get() + 1);
+            self.inner.get_iterator(query)
+        }
+
+        fn iterate_keys_only(&self, start_prefix: &[u8], end_prefix: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+            self.inner.iterate_keys_only(start_prefix, end_prefix)
+        }
+    }
+
+    #[test]
+    fn keys_only_iteration_matches_a_full_scan_without_reading_values() {
+        let store = MemoryStore::new();
+        for key in ["a", "ns/x", "ns/y", "nt"] {
+            store.put(key.as_bytes(), b"v").unwrap();
+        }
+        for (start, end) in [("", ""), ("ns/", "ns0"), ("ns/y", ""), ("z", "a")] {
+            let full = store.get_iterator(DataQuery::new()).unwrap().iterate_prefix(start.to_string(), end.to_string()).unwrap();
+            let full: Vec<Vec<u8>> = full.iter().map(|entry| entry.key().to_vec()).collect();
+            assert_eq!(store.iterate_keys_only(start.as_bytes(), end.as_bytes()).unwrap(), full, "{}..{}", start, end);
+        }
+
+        let counting = CountingStore { inner: store, iterators: Cell::new(0) };
+        let namespaced = PrefixStore::new(counting, "ns/");
+        assert_eq!(namespaced.iterate_keys_only(b"", b"").unwrap(), vec![b"x".to_vec(), b"y".to_vec()]);
+        assert_eq!(namespaced.iterate_keys_only(b"y", b"").unwrap(), vec![b"y".to_vec()]);
+        assert_eq!(namespaced.into_inner().iterators.get(), 0);
+    }
+}
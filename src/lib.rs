@@ -1,2 +1,2 @@
 pub mod config;
-mod datastore;
+pub mod datastore;